@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::*;
+
+/// CPU vendors that raplre can read RAPL domains from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Vendor {
+    Intel,
+    /// AMD family 17h (Zen) and later, including Hygon.
+    Amd,
+}
+
+/// Detect the vendor of the cpu in package `pkg`.
+pub fn detect_vendor(pkg: u16) -> Result<Vendor, RError> {
+    let cpu_info = match cpu::topology().package(pkg) {
+        Some(package) => &package.cpu_info,
+        None => {
+            crate::throw_rerr!(NOT_FOUND, "Package {} not found", pkg);
+        }
+    };
+
+    vendor_of(&cpu_info.vendor, cpu_info.ext_family as u32)
+}
+
+/// The vendor of a cpu from its cpuid vendor string and extended family.
+fn vendor_of(vendor: &str, ext_family: u32) -> Result<Vendor, RError> {
+    if vendor.contains("GenuineIntel") {
+        Ok(Vendor::Intel)
+    } else if vendor.contains("AuthenticAMD") || vendor.contains("HygonGenuine") {
+        // RAPL MSRs first appeared with Zen (family 17h).
+        if ext_family < 0x17 {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "Detected AMD cpu family {:#x}, raplre only supports AMD Zen (family 17h) and later.",
+                ext_family
+            );
+        }
+        Ok(Vendor::Amd)
+    } else {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "Detected `{}` cpu, raplre only supports Intel and AMD CPUs.",
+            vendor
+        );
+    }
+}
+
+/// Energy unit of the DRAM and PSYS domains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnergyUnit {
+    /// The energy status unit of `MSR_RAPL_POWER_UNIT`, shared with the package.
+    Package,
+    /// A fixed unit in Joules, independent of `MSR_RAPL_POWER_UNIT`.
+    Fixed(f64),
+}
+
+/// RAPL capabilities of an Intel microarchitecture.
+#[derive(Debug, Clone)]
+pub struct Microarch {
+    pub name: &'static str,
+    /// Family 06h models (extended model included).
+    pub models: &'static [u32],
+    /// MSR_PP0_ENERGY_STATUS
+    pub pp0: bool,
+    /// MSR_PP1_ENERGY_STATUS (graphics)
+    pub pp1: bool,
+    /// MSR_DRAM_ENERGY_STATUS
+    pub dram: bool,
+    pub dram_unit: EnergyUnit,
+    /// MSR_PLATFORM_ENERGY_STATUS (platform)
+    pub psys: bool,
+    pub psys_unit: EnergyUnit,
+}
+
+// Server parts report DRAM energy in 15.3 micro-joules (2^-16 J), regardless of
+// MSR_RAPL_POWER_UNIT.
+const DRAM_UNIT_SERVER: EnergyUnit = EnergyUnit::Fixed(0.0000152587890625);
+// Server parts report platform energy in Joules.
+const PSYS_UNIT_SERVER: EnergyUnit = EnergyUnit::Fixed(1.0);
+
+/// MSR_PKG_ENERGY_STATUS is available on every listed microarchitecture.
+pub const INTEL_MICROARCHS: &[Microarch] = &[
+    // Server
+    Microarch {
+        name: "Haswell-X",
+        models: &[0x3f],
+        pp0: false,
+        pp1: false,
+        dram: true,
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Broadwell-X",
+        models: &[0x4f, 0x56],
+        pp0: false,
+        pp1: false,
+        dram: true,
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Skylake-X",
+        // Skylake-SP, Cascade Lake, Cooper Lake
+        models: &[0x55],
+        pp0: false,
+        pp1: false,
+        dram: true,
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Ice Lake-X",
+        models: &[0x6a, 0x6c],
+        pp0: false,
+        pp1: false,
+        dram: true,
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Sapphire Rapids",
+        // Sapphire Rapids, Emerald Rapids
+        models: &[0x8f, 0xcf],
+        pp0: true,
+        pp1: false,
+        dram: true,
+        // Like the other server parts, as used by the intel_rapl and perf RAPL drivers.
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: true,
+        psys_unit: PSYS_UNIT_SERVER,
+    },
+    Microarch {
+        name: "Granite Rapids",
+        // Granite Rapids, Sierra Forest
+        models: &[0xad, 0xae, 0xaf],
+        pp0: false,
+        pp1: false,
+        dram: true,
+        dram_unit: DRAM_UNIT_SERVER,
+        psys: true,
+        psys_unit: PSYS_UNIT_SERVER,
+    },
+    // Client
+    Microarch {
+        name: "Haswell",
+        models: &[0x3c, 0x45, 0x46],
+        pp0: true,
+        pp1: true,
+        dram: false,
+        dram_unit: EnergyUnit::Package,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Broadwell",
+        models: &[0x3d, 0x47],
+        pp0: true,
+        pp1: true,
+        dram: false,
+        dram_unit: EnergyUnit::Package,
+        psys: false,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Skylake",
+        // Skylake, Kaby Lake, Coffee Lake, Comet Lake
+        models: &[0x4e, 0x5e, 0x8e, 0x9e, 0xa5, 0xa6],
+        pp0: true,
+        pp1: true,
+        dram: true,
+        dram_unit: EnergyUnit::Package,
+        psys: true,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Ice Lake",
+        // Ice Lake, Tiger Lake, Rocket Lake
+        models: &[0x7d, 0x7e, 0x8c, 0x8d, 0xa7],
+        pp0: true,
+        pp1: true,
+        dram: true,
+        dram_unit: EnergyUnit::Package,
+        psys: true,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Alder Lake",
+        // Alder Lake, Raptor Lake
+        models: &[0x97, 0x9a, 0xbe, 0xb7, 0xba, 0xbf],
+        pp0: true,
+        pp1: true,
+        dram: true,
+        dram_unit: EnergyUnit::Package,
+        psys: true,
+        psys_unit: EnergyUnit::Package,
+    },
+    Microarch {
+        name: "Meteor Lake",
+        // Meteor Lake, Lunar Lake, Arrow Lake
+        models: &[0xaa, 0xac, 0xbd, 0xc5, 0xc6, 0xb5],
+        pp0: true,
+        pp1: true,
+        dram: false,
+        dram_unit: EnergyUnit::Package,
+        psys: true,
+        psys_unit: EnergyUnit::Package,
+    },
+];
+
+/// Look up the microarchitecture of the Intel cpu in package `pkg`.
+pub fn intel_microarch(pkg: u16) -> Result<&'static Microarch, RError> {
+    let cpu_info = match cpu::topology().package(pkg) {
+        Some(package) => &package.cpu_info,
+        None => {
+            crate::throw_rerr!(NOT_FOUND, "Package {} not found", pkg);
+        }
+    };
+
+    find_intel_microarch(cpu_info.ext_family as u32, cpu_info.ext_model as u32)
+}
+
+/// The entry of [`INTEL_MICROARCHS`] of the Intel cpu `family` and `model`.
+fn find_intel_microarch(family: u32, model: u32) -> Result<&'static Microarch, RError> {
+    if family == 0x06 {
+        if let Some(arch) = INTEL_MICROARCHS.iter().find(|v| v.models.contains(&model)) {
+            return Ok(arch);
+        }
+    }
+
+    crate::throw_rerr!(
+        NOT_ALLOWED,
+        "Intel cpu family {:#x} model {:#x} is not supported",
+        family,
+        model
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendors() {
+        assert_eq!(vendor_of("GenuineIntel", 0x06).unwrap(), Vendor::Intel);
+        assert_eq!(vendor_of("AuthenticAMD", 0x19).unwrap(), Vendor::Amd);
+        assert_eq!(vendor_of("HygonGenuine", 0x18).unwrap(), Vendor::Amd);
+
+        let err = vendor_of("AuthenticAMD", 0x15).unwrap_err().to_string();
+        assert!(err.contains("family 0x15"), "{}", err);
+        let err = vendor_of("CentaurHauls", 0x06).unwrap_err().to_string();
+        assert!(err.contains("`CentaurHauls`"), "{}", err);
+    }
+
+    #[test]
+    fn intel_microarchs() {
+        // Skylake-SP: DRAM energy in 2^-16 J, whatever the package unit.
+        let server = find_intel_microarch(0x06, 0x55).unwrap();
+        assert_eq!(server.name, "Skylake-X");
+        assert!(server.dram && !server.pp0);
+        assert_eq!(server.dram_unit, EnergyUnit::Fixed(1.0 / 65536.0));
+
+        // Coffee Lake: DRAM energy in the package unit.
+        let client = find_intel_microarch(0x06, 0x9e).unwrap();
+        assert_eq!(client.name, "Skylake");
+        assert!(client.pp0 && client.dram);
+        assert_eq!(client.dram_unit, EnergyUnit::Package);
+
+        let err = find_intel_microarch(0x06, 0x01).unwrap_err().to_string();
+        assert!(err.contains("model 0x1 is not supported"), "{}", err);
+        assert!(find_intel_microarch(0x0f, 0x55).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{clock_tick_us, Attribution, PackageBusy};
+use crate::common::parse_cpu_list;
+use crate::error::*;
+
+/// Mount point of the cgroup v2 hierarchy.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The cgroup `path`, relative to [`CGROUP_ROOT`] unless it is already below it.
+pub fn resolve_cgroup(path: &Path) -> PathBuf {
+    if path.starts_with(CGROUP_ROOT) {
+        path.to_path_buf()
+    } else {
+        Path::new(CGROUP_ROOT).join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+/// Create the cgroup `name` below `parent`.
+///
+/// Processes moved into it, and their descendants, stay in it, so its CPU
+/// time is exactly theirs. Remove it with [`remove_cgroup`] once they exited.
+pub fn create_child_cgroup(parent: &Path, name: &str) -> Result<PathBuf, RError> {
+    let path = resolve_cgroup(parent).join(name);
+    fs::create_dir(&path).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to create cgroup {} (error: {})", path.display(), e),
+        )
+    })?;
+
+    Ok(path)
+}
+
+/// Remove an empty cgroup.
+pub fn remove_cgroup(path: &Path) -> Result<(), RError> {
+    fs::remove_dir(path).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to remove cgroup {} (error: {})", path.display(), e),
+        )
+    })
+}
+
+/// Attributes energy to a cgroup v2 group.
+///
+/// The CPU time of the group is `usage_usec` of its `cpu.stat`, which covers
+/// all its processes, including the ones that exited. `cpu.stat` does not tell
+/// on which cpus that time was spent, so it is split between the packages in
+/// proportion to the cpus of `cpuset.cpus.effective` on each of them, all cpus
+/// without a cpuset. The group's share of a package is its part of the CPU
+/// time relative to the busy time of the package; it is zero on the packages
+/// without any of its cpus.
+#[derive(Debug, Clone)]
+pub struct CgroupAttribution {
+    path: PathBuf,
+    busy: PackageBusy,
+    // part of the cpus of the group on every package, indexed by package
+    weights: Vec<f64>,
+    prev: u64,
+}
+
+impl CgroupAttribution {
+    /// Attribute energy to the cgroup `path`, see [`resolve_cgroup`].
+    pub fn open(path: &Path) -> Result<Self, RError> {
+        let path = resolve_cgroup(path);
+        let busy = PackageBusy::open()?;
+
+        let mut cpus = match fs::read_to_string(path.join("cpuset.cpus.effective")) {
+            Ok(cpus) => parse_cpu_list(&cpus)?,
+            Err(_) => vec![],
+        };
+        if cpus.is_empty() {
+            cpus = (0..busy.cpus()).collect();
+        }
+        let weights = package_weights(
+            cpus.iter().filter_map(|cpu| busy.package(*cpu)),
+            busy.packages(),
+        );
+
+        let prev = read_usage_usec(&path)?;
+        Ok(Self {
+            path,
+            busy,
+            weights,
+            prev,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Attribution for CgroupAttribution {
+    fn model(&self) -> String {
+        format!(
+            "share of the busy cpu time (/proc/stat) of every package used by the cgroup {} (usage_usec of cpu.stat, split between the packages by its cpus in cpuset.cpus.effective)",
+            self.path.display()
+        )
+    }
+
+    fn sample(&mut self) -> Result<Vec<f64>, RError> {
+        let busy = self.busy.sample()?;
+        let usage = read_usage_usec(&self.path)?;
+        let used_us = usage.saturating_sub(self.prev);
+        self.prev = usage;
+
+        let tick_us = clock_tick_us();
+        let busy_us: Vec<f64> = busy.iter().map(|busy| *busy as f64 * tick_us).collect();
+
+        Ok(package_shares(used_us as f64, &busy_us, &self.weights))
+    }
+}
+
+/// The part of `packages`, the package of every cpu, on each of the `count` packages.
+fn package_weights<I: Iterator<Item = u16>>(packages: I, count: usize) -> Vec<f64> {
+    let mut cpus = vec![0u32; count];
+    for pkg in packages {
+        if let Some(n) = cpus.get_mut(pkg as usize) {
+            *n += 1;
+        }
+    }
+
+    let total: u32 = cpus.iter().sum();
+    cpus.iter()
+        .map(|n| match total {
+            0 => 0.0,
+            total => *n as f64 / total as f64,
+        })
+        .collect()
+}
+
+/// Share of every package for `used_us` of CPU time split by `weights`, given
+/// the busy time of every package, at most 1.
+fn package_shares(used_us: f64, busy_us: &[f64], weights: &[f64]) -> Vec<f64> {
+    weights
+        .iter()
+        .zip(busy_us.iter())
+        .map(|(weight, busy_us)| {
+            if *busy_us > 0.0 {
+                (used_us * weight / busy_us).min(1.0)
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+fn read_usage_usec(path: &Path) -> Result<u64, RError> {
+    let file = path.join("cpu.stat");
+    let stat = fs::read_to_string(&file).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to read {} (error: {})", file.display(), e),
+        )
+    })?;
+
+    match parse_usage_usec(&stat) {
+        Some(usage) => Ok(usage),
+        None => {
+            crate::throw_rerr!(INVALID_VALUE, "No usage_usec in {}", file.display());
+        }
+    }
+}
+
+fn parse_usage_usec(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .and_then(|usage| usage.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cgroup_paths() {
+        assert_eq!(
+            resolve_cgroup(Path::new("system.slice/foo.service")),
+            PathBuf::from("/sys/fs/cgroup/system.slice/foo.service")
+        );
+        assert_eq!(
+            resolve_cgroup(Path::new("/user.slice")),
+            PathBuf::from("/sys/fs/cgroup/user.slice")
+        );
+        assert_eq!(
+            resolve_cgroup(Path::new("/sys/fs/cgroup/user.slice")),
+            PathBuf::from("/sys/fs/cgroup/user.slice")
+        );
+        assert_eq!(
+            parse_usage_usec("usage_usec 1234\nuser_usec 1000\nsystem_usec 234\n"),
+            Some(1234)
+        );
+    }
+
+    #[test]
+    fn split_between_packages() {
+        // 3 cpus on package 0, 1 on package 1, none on package 2.
+        let weights = package_weights([0, 0, 1, 0].into_iter(), 3);
+        assert_eq!(weights, vec![0.75, 0.25, 0.0]);
+        assert_eq!(package_weights([].into_iter(), 2), vec![0.0, 0.0]);
+
+        let shares = package_shares(400.0, &[1000.0, 400.0, 800.0], &weights);
+        assert_eq!(shares, vec![0.3, 0.25, 0.0]);
+        // At most 1, and nothing on an idle package.
+        assert_eq!(package_shares(400.0, &[100.0, 0.0], &[1.0, 0.0]), vec![1.0, 0.0]);
+    }
+}
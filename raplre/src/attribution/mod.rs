@@ -0,0 +1,148 @@
+use std::fs;
+
+use crate::common::package_of_core;
+use crate::error::*;
+
+mod cgroup;
+mod process;
+pub use cgroup::*;
+pub use process::*;
+
+/// Domains whose energy is attributed, the ones driven by the activity of the cpus.
+pub(crate) const ATTRIBUTED_DOMAINS: [&str; 3] = ["Package", "Pp0", "Dram"];
+
+/// Apportions the energy of every package to a workload, by its share of the
+/// busy CPU time of the package.
+///
+/// A session with an attribution samples it together with the energy
+/// counters, and attributes `share * Δenergy` of every interval to the
+/// workload, see [`crate::Session::set_attribution`].
+pub trait Attribution: Send {
+    /// How the share of the workload is measured, written to the run summary.
+    fn model(&self) -> String;
+
+    /// The share (0 to 1) of the busy CPU time of every package used by the
+    /// workload since the previous call, indexed by package.
+    fn sample(&mut self) -> Result<Vec<f64>, RError>;
+}
+
+/// Busy CPU time of every package, from the per-cpu lines of `/proc/stat`.
+///
+/// Times are in clock ticks (`USER_HZ`), like the CPU times of `/proc/<pid>/stat`.
+#[derive(Debug, Clone)]
+pub(crate) struct PackageBusy {
+    // package of every cpu, indexed by cpu
+    packages: Vec<Option<u16>>,
+    prev: Vec<u64>,
+}
+
+impl PackageBusy {
+    pub(crate) fn open() -> Result<Self, RError> {
+        let cpus = read_cpu_busy()?;
+        let max_cpu = cpus.iter().map(|(cpu, _)| *cpu).max().unwrap_or(0);
+        let packages = (0..=max_cpu).map(package_of_core).collect();
+
+        let mut busy = Self {
+            packages,
+            prev: vec![0; cpu::topology().max_num_packages() as usize],
+        };
+        busy.prev = busy.per_package(&cpus);
+
+        Ok(busy)
+    }
+
+    /// The package of `cpu`.
+    pub(crate) fn package(&self, cpu: u32) -> Option<u16> {
+        self.packages.get(cpu as usize).cloned().flatten()
+    }
+
+    /// Busy ticks of every package since the previous call, indexed by package.
+    pub(crate) fn sample(&mut self) -> Result<Vec<u64>, RError> {
+        let cur = self.per_package(&read_cpu_busy()?);
+        let delta = cur
+            .iter()
+            .zip(self.prev.iter())
+            .map(|(cur, prev)| cur.saturating_sub(*prev))
+            .collect();
+        self.prev = cur;
+
+        Ok(delta)
+    }
+
+    pub(crate) fn packages(&self) -> usize {
+        self.prev.len()
+    }
+
+    /// Number of cpus, offline cpus below the highest online one included.
+    pub(crate) fn cpus(&self) -> u32 {
+        self.packages.len() as u32
+    }
+
+    fn per_package(&self, cpus: &[(u32, u64)]) -> Vec<u64> {
+        let mut out = vec![0; self.prev.len()];
+        for (cpu, busy) in cpus {
+            if let Some(pkg) = self.package(*cpu) {
+                if let Some(total) = out.get_mut(pkg as usize) {
+                    *total += busy;
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn read_cpu_busy() -> Result<Vec<(u32, u64)>, RError> {
+    let stat = fs::read_to_string("/proc/stat").map_err(|e| {
+        new_custom_msg(IOERR, format!("Failed to read /proc/stat (error: {})", e))
+    })?;
+
+    Ok(stat.lines().filter_map(parse_cpu_line).collect())
+}
+
+/// Parse a `cpuN` line of `/proc/stat` into the cpu and its busy ticks.
+///
+/// Busy time is everything but idle and iowait. Guest time is already
+/// accounted in user time.
+fn parse_cpu_line(line: &str) -> Option<(u32, u64)> {
+    let mut fields = line.split_whitespace();
+    let cpu = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+    let ticks: Vec<u64> = fields.take(8).map(|f| f.parse().unwrap_or(0)).collect();
+    if ticks.len() < 8 {
+        return None;
+    }
+
+    // user nice system idle iowait irq softirq steal
+    Some((cpu, ticks[0] + ticks[1] + ticks[2] + ticks[5] + ticks[6] + ticks[7]))
+}
+
+/// Length of a clock tick (`USER_HZ`), in microseconds.
+pub(crate) fn clock_tick_us() -> f64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        hz if hz > 0 => 1e6 / hz as f64,
+        _ => 1e4,
+    }
+}
+
+/// `part` as a share of `total`, at most 1.
+pub(crate) fn share(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => (part as f64 / total as f64).min(1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_line() {
+        assert_eq!(
+            parse_cpu_line("cpu3 100 5 20 9000 40 1 2 3 0 0"),
+            Some((3, 131))
+        );
+        assert_eq!(parse_cpu_line("cpu 400 20 80 36000 160 4 8 12 0 0"), None);
+        assert_eq!(parse_cpu_line("intr 12345"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use super::{share, Attribution, PackageBusy};
+use crate::error::*;
+
+/// The process whose tree [`ProcessAttribution`] attributes energy to.
+///
+/// The target can be set after the session has started, so that the session
+/// covers the whole lifetime of a process spawned afterwards. Nothing is
+/// attributed until it is set.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTarget(Arc<AtomicU32>);
+
+impl ProcessTarget {
+    pub fn set(&self, pid: u32) {
+        self.0.store(pid, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Option<u32> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+}
+
+/// Attributes energy to a process and all its descendants.
+///
+/// Every sample walks the process tree through `/proc/<pid>/task/<tid>/children`,
+/// and reads the CPU time (utime + stime) and the cpu last run on (field 39)
+/// of every thread from `/proc/<pid>/task/<tid>/stat`. The CPU time a thread
+/// used in the interval is counted on the package of that cpu.
+///
+/// Threads that exit between two samples lose their CPU time of that interval.
+#[derive(Debug, Clone)]
+pub struct ProcessAttribution {
+    target: ProcessTarget,
+    busy: PackageBusy,
+    // CPU time of every thread of the tree at the previous sample, by tid
+    prev: HashMap<u32, u64>,
+}
+
+impl ProcessAttribution {
+    pub fn new(target: ProcessTarget) -> Result<Self, RError> {
+        Ok(Self {
+            target,
+            busy: PackageBusy::open()?,
+            prev: HashMap::new(),
+        })
+    }
+}
+
+impl Attribution for ProcessAttribution {
+    fn model(&self) -> String {
+        "share of the busy cpu time of the package (/proc/stat) used by the threads of the process tree (/proc/<pid>/task/<tid>/stat), by the cpu each thread last ran on".to_string()
+    }
+
+    fn sample(&mut self) -> Result<Vec<f64>, RError> {
+        let total = self.busy.sample()?;
+        let mut used = vec![0u64; self.busy.packages()];
+
+        if let Some(pid) = self.target.get() {
+            let mut cur = HashMap::new();
+            for thread in process_tree_threads(pid) {
+                let prev = self.prev.get(&thread.tid).cloned().unwrap_or(0);
+                if let Some(pkg) = self.busy.package(thread.cpu) {
+                    if let Some(used) = used.get_mut(pkg as usize) {
+                        *used += thread.ticks.saturating_sub(prev);
+                    }
+                }
+                cur.insert(thread.tid, thread.ticks);
+            }
+            self.prev = cur;
+        }
+
+        Ok(used
+            .iter()
+            .zip(total.iter())
+            .map(|(used, total)| share(*used, *total))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ThreadStat {
+    tid: u32,
+    // utime + stime, in clock ticks
+    ticks: u64,
+    // cpu the thread last ran on
+    cpu: u32,
+}
+
+/// The threads of `root` and of all its descendants.
+///
+/// Processes that exit while the tree is walked are skipped.
+fn process_tree_threads(root: u32) -> Vec<ThreadStat> {
+    let mut out = vec![];
+    let mut pids = vec![root];
+    while let Some(pid) = pids.pop() {
+        let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+            Ok(tasks) => tasks,
+            Err(_) => continue,
+        };
+
+        for task in tasks.flatten() {
+            let path = task.path();
+            if let Some(thread) = read_thread_stat(&path) {
+                out.push(thread);
+            }
+            if let Ok(children) = fs::read_to_string(path.join("children")) {
+                pids.extend(children.split_whitespace().filter_map(|c| c.parse::<u32>().ok()));
+            }
+        }
+    }
+
+    out
+}
+
+fn read_thread_stat(task: &Path) -> Option<ThreadStat> {
+    parse_thread_stat(&fs::read_to_string(task.join("stat")).ok()?)
+}
+
+/// Parse a `/proc/<pid>/task/<tid>/stat` line.
+fn parse_thread_stat(stat: &str) -> Option<ThreadStat> {
+    let tid = stat.split_whitespace().next()?.parse().ok()?;
+    // The command name (field 2) may contain spaces and parentheses, the
+    // other fields start after its last ')', with field 3 (state).
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3).and_then(|f| f.parse::<u64>().ok());
+
+    Some(ThreadStat {
+        tid,
+        ticks: field(14)? + field(15)?,
+        cpu: field(39)? as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_stat() {
+        let stat = "4242 (a (b) c) R 1 4242 4242 0 -1 4194304 120 0 0 0 250 30 0 0 20 0 4 0 \
+                    1000 10000000 500 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 7 0 0 0 0 0";
+        assert_eq!(
+            parse_thread_stat(stat),
+            Some(ThreadStat {
+                tid: 4242,
+                ticks: 280,
+                cpu: 7,
+            })
+        );
+        assert_eq!(parse_thread_stat("4242 (a) R 1"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::parse_duration;
+use crate::error::*;
+use crate::limits::PowerLimit;
+
+mod msr;
+mod powercap;
+mod simulated;
+pub use msr::*;
+pub use powercap::*;
+pub use simulated::*;
+
+/// A power cap requested for a RAPL domain, e.g. `Package=120W,window=1s`.
+///
+/// `zone` is either a domain (`Package`), which caps the domain of every
+/// package, or a single zone (`Package-0`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerCap {
+    pub zone: String,
+    pub power_w: f64,
+    /// Keep the current time window if not set.
+    pub time_window_s: Option<f64>,
+}
+
+impl FromStr for PowerCap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let (zone, power) = match parts.next().and_then(|v| v.split_once('=')) {
+            Some(v) => v,
+            None => return Err(format!("invalid power cap `{}` (expected <ZONE>=<W>W)", s)),
+        };
+        let power_w: f64 = power
+            .trim()
+            .trim_end_matches(['W', 'w'])
+            .parse()
+            .map_err(|e| format!("invalid power `{}` (error: {})", power, e))?;
+        if power_w <= 0.0 {
+            return Err(format!("invalid power `{}` (must be positive)", power));
+        }
+
+        let mut time_window_s = None;
+        for part in parts {
+            match part.split_once('=') {
+                Some(("window", window)) => {
+                    let window = parse_duration(window).map_err(|e| e.to_string())?;
+                    time_window_s = Some(window.as_secs_f64());
+                }
+                _ => return Err(format!("invalid power cap option `{}`", part)),
+            }
+        }
+
+        Ok(Self {
+            zone: zone.trim().to_string(),
+            power_w,
+            time_window_s,
+        })
+    }
+}
+
+impl Display for PowerCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}W", self.zone, self.power_w)?;
+        if let Some(window) = self.time_window_s {
+            write!(f, ",window={}s", window)?;
+        }
+        Ok(())
+    }
+}
+
+/// Backends that raplre can write power limits through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapBackend {
+    /// The power limit MSRs, written through `/dev/cpu/*/msr`.
+    #[default]
+    Msr,
+    /// The `constraint_0_*` (long term) files of the powercap zones.
+    Powercap,
+    /// In-memory power limits, nothing is written to the hardware.
+    Simulated,
+}
+
+impl Display for CapBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CapBackend::Msr => write!(f, "msr"),
+            CapBackend::Powercap => write!(f, "powercap"),
+            CapBackend::Simulated => write!(f, "simulated"),
+        }
+    }
+}
+
+impl FromStr for CapBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "msr" => Ok(CapBackend::Msr),
+            "powercap" => Ok(CapBackend::Powercap),
+            "simulated" => Ok(CapBackend::Simulated),
+            _ => Err(format!(
+                "unknown cap backend `{}` (expected one of: msr, powercap, simulated)",
+                s
+            )),
+        }
+    }
+}
+
+/// The long term (PL1) limit of a zone, as read from a [`CapControl`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimitState {
+    pub limit: PowerLimit,
+    /// The raw register value for the MSR backend, so it can be restored as is.
+    pub raw: u64,
+}
+
+/// Read and write the long term power limit of RAPL zones.
+pub trait CapControl: Send + Sync {
+    /// The backend implemented by this control.
+    fn backend(&self) -> CapBackend;
+
+    /// The zones matched by `zone`, see [`PowerCap`].
+    fn zones(&self, zone: &str) -> Result<Vec<String>, RError>;
+
+    /// Read the current limit of `zone`.
+    fn read(&self, zone: &str) -> Result<LimitState, RError>;
+
+    /// The state of `zone` after applying `cap` to `state`.
+    fn capped(&self, zone: &str, state: &LimitState, cap: &PowerCap) -> Result<LimitState, RError>;
+
+    /// Write `state` to `zone`.
+    fn write(&self, zone: &str, state: &LimitState) -> Result<(), RError>;
+}
+
+/// Open the power limit control of `backend`.
+pub fn open_cap_control(backend: CapBackend) -> Result<Box<dyn CapControl>, RError> {
+    match backend {
+        CapBackend::Msr => Ok(Box::new(MsrCapControl::new())),
+        CapBackend::Powercap => Ok(Box::new(PowercapCapControl::new())),
+        CapBackend::Simulated => Ok(Box::new(SimulatedCapControl::new())),
+    }
+}
+
+/// A power cap applied to a zone for the duration of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedCap {
+    pub zone: String,
+    pub backend: CapBackend,
+    pub requested: PowerCap,
+    pub original: PowerLimit,
+    pub applied: PowerLimit,
+    /// Nothing was written to the zone.
+    pub dry_run: bool,
+}
+
+/// Power caps applied to a set of zones.
+///
+/// The original limits are restored by [`CapGuard::restore`], or when the
+/// guard is dropped, e.g. while unwinding from an error.
+pub struct CapGuard<'a> {
+    control: &'a dyn CapControl,
+    saved: Vec<(String, LimitState)>,
+    applied: Vec<AppliedCap>,
+}
+
+impl<'a> CapGuard<'a> {
+    /// Apply `caps` through `control`. With `dry_run`, the caps are computed
+    /// and reported, but nothing is written.
+    pub fn apply(
+        control: &'a dyn CapControl,
+        caps: &[PowerCap],
+        dry_run: bool,
+    ) -> Result<Self, RError> {
+        let mut guard = Self {
+            control,
+            saved: vec![],
+            applied: vec![],
+        };
+
+        for cap in caps {
+            let zones = guard.control.zones(&cap.zone)?;
+            if zones.is_empty() {
+                crate::throw_rerr!(NOT_FOUND, "No zone matches power cap `{}`", cap);
+            }
+
+            for zone in zones {
+                let original = guard.control.read(&zone)?;
+                let capped = guard.control.capped(&zone, &original, cap)?;
+
+                if !dry_run {
+                    // Save first, a partially written limit is restored as well.
+                    guard.saved.push((zone.clone(), original.clone()));
+                    guard.control.write(&zone, &capped)?;
+                }
+
+                guard.applied.push(AppliedCap {
+                    zone,
+                    backend: guard.control.backend(),
+                    requested: cap.clone(),
+                    original: original.limit,
+                    applied: capped.limit,
+                    dry_run,
+                });
+            }
+        }
+
+        Ok(guard)
+    }
+
+    pub fn applied(&self) -> &[AppliedCap] {
+        &self.applied
+    }
+
+    /// Restore the original limits of all capped zones.
+    pub fn restore(mut self) -> Result<(), RError> {
+        self.restore_saved()
+    }
+
+    fn restore_saved(&mut self) -> Result<(), RError> {
+        let mut res = Ok(());
+        // Restore in reverse order, a zone capped twice ends up with its first saved value.
+        while let Some((zone, state)) = self.saved.pop() {
+            if let Err(e) = self.control.write(&zone, &state) {
+                if res.is_ok() {
+                    res = Err(e);
+                } else {
+                    crate::ignore_rerr!(e);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl Drop for CapGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore_saved() {
+            eprintln!("ERROR:Failed to restore power limits ({})", e);
+        }
+    }
+}
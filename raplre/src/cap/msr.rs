@@ -0,0 +1,115 @@
+use crate::arch::{detect_vendor, Vendor};
+use crate::common::get_core_of_package;
+use crate::error::*;
+use crate::limits::{
+    read_rapl_units, PowerLimit, MSR_DRAM_POWER_LIMIT, MSR_PKG_POWER_LIMIT, MSR_PP0_POWER_LIMIT,
+    MSR_RAPL_POWER_UNIT,
+};
+
+use super::{CapBackend, CapControl, LimitState, PowerCap};
+
+/// Power limits written to `MSR_PKG_POWER_LIMIT`, `MSR_DRAM_POWER_LIMIT` and
+/// `MSR_PP0_POWER_LIMIT`. Only the first (PL1) limit is changed.
+#[derive(Debug, Default)]
+pub struct MsrCapControl {}
+
+impl MsrCapControl {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// The core, register and lock bit of the power limit of `zone`.
+fn power_limit_register(zone: &str) -> Result<(u32, u32, u64), RError> {
+    let (domain, pkg) = match zone.split_once('-').map(|(d, p)| (d, p.parse::<u16>())) {
+        Some((domain, Ok(pkg))) => (domain, pkg),
+        _ => {
+            crate::throw_rerr!(INVALID_VALUE, "Invalid zone `{}`", zone);
+        }
+    };
+
+    if detect_vendor(pkg)? != Vendor::Intel {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "RAPL power limits are only supported on Intel CPUs"
+        );
+    }
+
+    let core = match get_core_of_package(pkg) {
+        Some(core) => core,
+        None => {
+            crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+        }
+    };
+
+    match domain {
+        "Package" => Ok((core, MSR_PKG_POWER_LIMIT, 1 << 63)),
+        "Dram" => Ok((core, MSR_DRAM_POWER_LIMIT, 1 << 31)),
+        "Pp0" => Ok((core, MSR_PP0_POWER_LIMIT, 1 << 31)),
+        _ => {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "Zone `{}` has no power limit MSR (expected Package, Dram or Pp0)",
+                zone
+            );
+        }
+    }
+}
+
+impl CapControl for MsrCapControl {
+    fn backend(&self) -> CapBackend {
+        CapBackend::Msr
+    }
+
+    fn zones(&self, zone: &str) -> Result<Vec<String>, RError> {
+        if zone.contains('-') {
+            return Ok(vec![zone.to_string()]);
+        }
+
+        Ok((0..cpu::topology().max_num_packages())
+            .map(|pkg| format!("{}-{}", zone, pkg))
+            .collect())
+    }
+
+    fn read(&self, zone: &str) -> Result<LimitState, RError> {
+        let (core, which, _) = power_limit_register(zone)?;
+        let units = read_rapl_units(core, MSR_RAPL_POWER_UNIT)?;
+        let raw = crate::cpuid::read_msr(core, which)?;
+
+        Ok(LimitState {
+            limit: PowerLimit::decode(raw, &units),
+            raw,
+        })
+    }
+
+    fn capped(&self, zone: &str, state: &LimitState, cap: &PowerCap) -> Result<LimitState, RError> {
+        let (core, _, lock) = power_limit_register(zone)?;
+        if state.raw & lock != 0 {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "The power limit of `{}` is locked until the next reset",
+                zone
+            );
+        }
+
+        let units = read_rapl_units(core, MSR_RAPL_POWER_UNIT)?;
+        let limit = PowerLimit {
+            power_w: cap.power_w,
+            time_window_s: cap.time_window_s.unwrap_or(state.limit.time_window_s),
+            enabled: true,
+            clamped: state.limit.clamped,
+        };
+        let raw = limit.encode_into(state.raw, &units);
+
+        Ok(LimitState {
+            // Report the limit as rounded by the encoding.
+            limit: PowerLimit::decode(raw, &units),
+            raw,
+        })
+    }
+
+    fn write(&self, zone: &str, state: &LimitState) -> Result<(), RError> {
+        let (core, which, _) = power_limit_register(zone)?;
+        crate::cpuid::write_msr(core, which, state.raw)
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::*;
+use crate::limits::PowerLimit;
+use crate::source::{EnergySource, PowercapSource};
+
+use super::{CapBackend, CapControl, LimitState, PowerCap};
+
+/// Power limits written to the long term constraint (`constraint_0_*`) of
+/// the powercap zones. A capped zone is enabled, like the limit MSRs.
+#[derive(Debug, Default)]
+pub struct PowercapCapControl {
+    source: PowercapSource,
+}
+
+impl PowercapCapControl {
+    pub fn new() -> Self {
+        Self {
+            source: PowercapSource::new(),
+        }
+    }
+
+    /// Create a control writing zones below `root` instead of `/sys/class/powercap`.
+    pub fn with_root<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            source: PowercapSource::with_root(root),
+        }
+    }
+
+    fn zone_path(&self, zone: &str) -> Result<PathBuf, RError> {
+        match self
+            .source
+            .domains()?
+            .into_iter()
+            .find(|v| v.name == zone)
+            .and_then(|v| v.path)
+        {
+            Some(path) => Ok(path),
+            None => {
+                crate::throw_rerr!(NOT_FOUND, "Powercap zone `{}` not found", zone);
+            }
+        }
+    }
+}
+
+fn read_u64(path: &PathBuf) -> Result<u64, RError> {
+    let value = fs::read_to_string(path).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to read {} (error: {})", path.display(), e),
+        )
+    })?;
+
+    value.trim().parse().map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!("Failed to parse {} (error: {})", path.display(), e),
+        )
+    })
+}
+
+fn write_u64(path: &PathBuf, value: u64) -> Result<(), RError> {
+    fs::write(path, value.to_string()).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to write {} (error: {})", path.display(), e),
+        )
+    })
+}
+
+impl CapControl for PowercapCapControl {
+    fn backend(&self) -> CapBackend {
+        CapBackend::Powercap
+    }
+
+    fn zones(&self, zone: &str) -> Result<Vec<String>, RError> {
+        Ok(self
+            .source
+            .domains()?
+            .into_iter()
+            .map(|v| v.name)
+            .filter(|name| name == zone || name.split('-').next() == Some(zone))
+            .collect())
+    }
+
+    fn read(&self, zone: &str) -> Result<LimitState, RError> {
+        let path = self.zone_path(zone)?;
+        let power_uw = read_u64(&path.join("constraint_0_power_limit_uw"))?;
+        let window_us = read_u64(&path.join("constraint_0_time_window_us"))?;
+        let enabled = read_u64(&path.join("enabled")).unwrap_or(1) != 0;
+
+        Ok(LimitState {
+            limit: PowerLimit {
+                power_w: power_uw as f64 / 1e6,
+                time_window_s: window_us as f64 / 1e6,
+                enabled,
+                clamped: false,
+            },
+            raw: 0,
+        })
+    }
+
+    fn capped(&self, _: &str, state: &LimitState, cap: &PowerCap) -> Result<LimitState, RError> {
+        Ok(LimitState {
+            limit: PowerLimit {
+                power_w: cap.power_w,
+                time_window_s: cap.time_window_s.unwrap_or(state.limit.time_window_s),
+                enabled: true,
+                clamped: state.limit.clamped,
+            },
+            raw: 0,
+        })
+    }
+
+    fn write(&self, zone: &str, state: &LimitState) -> Result<(), RError> {
+        let path = self.zone_path(zone)?;
+        // Zones without an `enabled` file are always enabled.
+        let enabled = path.join("enabled");
+        let has_enabled = enabled.exists();
+
+        // Disable before and enable after writing the limit, so that it is
+        // never enforced half written.
+        if has_enabled && !state.limit.enabled {
+            write_u64(&enabled, 0)?;
+        }
+        write_u64(
+            &path.join("constraint_0_time_window_us"),
+            (state.limit.time_window_s * 1e6).round() as u64,
+        )?;
+        write_u64(
+            &path.join("constraint_0_power_limit_uw"),
+            (state.limit.power_w * 1e6).round() as u64,
+        )?;
+        if has_enabled && state.limit.enabled {
+            write_u64(&enabled, 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cap::CapGuard;
+    use crate::source::testing::FakeRoot;
+
+    #[test]
+    fn cap_and_restore_disabled_zone() {
+        let root = FakeRoot::new("cap-powercap");
+        root.powercap_zone("intel-rapl:0", "package-0", 1000, 262143328850);
+        root.write("intel-rapl:0/enabled", 0);
+        root.write("intel-rapl:0/constraint_0_power_limit_uw", 125000000);
+        root.write("intel-rapl:0/constraint_0_time_window_us", 999424);
+
+        let control = PowercapCapControl::with_root(root.path());
+        let cap: PowerCap = "Package=80W,window=0.5s".parse().unwrap();
+        let guard = CapGuard::apply(&control, &[cap], false).unwrap();
+        assert!(guard.applied()[0].applied.enabled);
+        assert_eq!(root.read("intel-rapl:0/enabled"), "1");
+        assert_eq!(
+            root.read("intel-rapl:0/constraint_0_power_limit_uw"),
+            "80000000"
+        );
+        assert_eq!(
+            root.read("intel-rapl:0/constraint_0_time_window_us"),
+            "500000"
+        );
+
+        guard.restore().unwrap();
+        assert_eq!(root.read("intel-rapl:0/enabled"), "0");
+        assert_eq!(
+            root.read("intel-rapl:0/constraint_0_power_limit_uw"),
+            "125000000"
+        );
+        assert_eq!(
+            root.read("intel-rapl:0/constraint_0_time_window_us"),
+            "999424"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::*;
+use crate::limits::PowerLimit;
+
+use super::{CapBackend, CapControl, LimitState, PowerCap};
+
+/// In-memory power limits, to exercise power capping without hardware.
+#[derive(Debug)]
+pub struct SimulatedCapControl {
+    zones: Mutex<HashMap<String, LimitState>>,
+}
+
+impl SimulatedCapControl {
+    /// A single package with a 125 W PL1 over 1 s and an unlimited DRAM zone.
+    pub fn new() -> Self {
+        let mut zones = HashMap::new();
+        zones.insert(
+            "Package-0".to_string(),
+            LimitState {
+                limit: PowerLimit {
+                    power_w: 125.0,
+                    time_window_s: 1.0,
+                    enabled: true,
+                    clamped: false,
+                },
+                raw: 0,
+            },
+        );
+        zones.insert(
+            "Dram-0".to_string(),
+            LimitState {
+                limit: PowerLimit {
+                    power_w: 0.0,
+                    time_window_s: 0.0009765625,
+                    enabled: false,
+                    clamped: false,
+                },
+                raw: 0,
+            },
+        );
+
+        Self::with_zones(zones)
+    }
+
+    pub fn with_zones(zones: HashMap<String, LimitState>) -> Self {
+        Self {
+            zones: Mutex::new(zones),
+        }
+    }
+}
+
+impl Default for SimulatedCapControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CapControl for SimulatedCapControl {
+    fn backend(&self) -> CapBackend {
+        CapBackend::Simulated
+    }
+
+    fn zones(&self, zone: &str) -> Result<Vec<String>, RError> {
+        let mut zones: Vec<String> = self
+            .zones
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|name| *name == zone || name.split('-').next() == Some(zone))
+            .cloned()
+            .collect();
+        zones.sort();
+
+        Ok(zones)
+    }
+
+    fn read(&self, zone: &str) -> Result<LimitState, RError> {
+        match self.zones.lock().unwrap().get(zone) {
+            Some(state) => Ok(state.clone()),
+            None => {
+                crate::throw_rerr!(NOT_FOUND, "Simulated zone `{}` not found", zone);
+            }
+        }
+    }
+
+    fn capped(&self, _: &str, state: &LimitState, cap: &PowerCap) -> Result<LimitState, RError> {
+        Ok(LimitState {
+            limit: PowerLimit {
+                power_w: cap.power_w,
+                time_window_s: cap.time_window_s.unwrap_or(state.limit.time_window_s),
+                enabled: true,
+                clamped: state.limit.clamped,
+            },
+            raw: 0,
+        })
+    }
+
+    fn write(&self, zone: &str, state: &LimitState) -> Result<(), RError> {
+        match self.zones.lock().unwrap().get_mut(zone) {
+            Some(v) => {
+                *v = state.clone();
+                Ok(())
+            }
+            None => {
+                crate::throw_rerr!(NOT_FOUND, "Simulated zone `{}` not found", zone);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cap::CapGuard;
+
+    #[test]
+    fn apply_and_restore() {
+        let control = SimulatedCapControl::new();
+        let caps: Vec<PowerCap> = vec!["Package=100W,window=2s".parse().unwrap()];
+
+        let guard = CapGuard::apply(&control, &caps, false).unwrap();
+        assert_eq!(guard.applied().len(), 1);
+        assert_eq!(guard.applied()[0].original.power_w, 125.0);
+        let state = control.read("Package-0").unwrap();
+        assert_eq!(state.limit.power_w, 100.0);
+        assert_eq!(state.limit.time_window_s, 2.0);
+
+        guard.restore().unwrap();
+        assert_eq!(control.read("Package-0").unwrap().limit.power_w, 125.0);
+
+        // Nothing is written in dry-run mode, dropping the guard restores as well.
+        {
+            let _guard = CapGuard::apply(&control, &caps, true).unwrap();
+            assert_eq!(control.read("Package-0").unwrap().limit.power_w, 125.0);
+            let _guard = CapGuard::apply(&control, &caps, false).unwrap();
+            assert_eq!(control.read("Package-0").unwrap().limit.power_w, 100.0);
+        }
+        assert_eq!(control.read("Package-0").unwrap().limit.power_w, 125.0);
+    }
+}
@@ -1,149 +1,121 @@
 use std::{
     collections::HashMap,
-    path::PathBuf,
-    time::{Instant, SystemTime},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
+use crate::error::*;
+use crate::limits::{read_power_info, PowerInfo};
 use crate::models::{self, IsolateData, RAPLZone};
+use crate::stats::Stats;
+use crate::source::{
+    open_source, probe, probe_domains, Backend, EnergySource, ZoneProbe, PROBE_WINDOW,
+};
 
 // measurement thread
 pub(crate) const THREAD_KILL: i8 = 1;
 
-// ncurses
-const COLOUR_BLACK: i16 = 0;
-const DEFAULT_COLOUR: i16 = -1;
-pub(crate) const HEADER_PAIR: i16 = 1;
-pub(crate) const KEY_CODE_EXIT: i32 = 113; // q
-
-pub(crate) fn setup_ncurses() {
-    let w = ncurses::initscr();
-    ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
-    ncurses::nodelay(w, true);
-
-    if ncurses::has_colors() {
-        ncurses::start_color();
-        ncurses::init_pair(HEADER_PAIR, COLOUR_BLACK, DEFAULT_COLOUR);
-    }
-}
-
-pub(crate) fn kill_ncurses() {
-    ncurses::endwin();
-    ncurses::reset_shell_mode();
-}
-
-pub(crate) fn should_terminate(limit: u64, now: Instant, start_time: Instant) -> bool {
-    return limit > 0 && now.duration_since(start_time).as_secs() >= limit;
-}
-
-pub(crate) fn terminate() {
-    kill_ncurses();
-}
-
-pub(crate) fn setup_rapl_data() -> Vec<models::RAPLData> {
-    detect_cpu()
+pub(crate) fn setup_rapl_data(source: &dyn EnergySource) -> Result<Vec<models::RAPLData>, RError> {
+    Ok(probe_domains(source)?
         .into_iter()
         .map(|zone| models::RAPLData {
             zone_name: zone.name.clone(),
-            time_elapsed: 0,
+            time_elapsed: 0.0,
+            timestamp_ns: 0,
+            read_time: None,
+            read_latency: Duration::ZERO,
             zone: zone,
             prev_power_read: 0,
             total_power_j: 0.0,
             watt: 0.0,
             avg_watt: 0.0,
             delta_power_j: 0.0,
+            total_raw: 0,
+            gap: false,
+            gaps: 0,
+            temp: None,
+            core_temp_max: None,
+            freq_mhz: None,
+            busy_percent: None,
+            pc2_percent: None,
+            pc3_percent: None,
+            pc6_percent: None,
+            pc7_percent: None,
+            pc8_percent: None,
+            pc9_percent: None,
+            pc10_percent: None,
+            core_c6_percent: None,
+            prev_throttle_read: 0,
+            throttled_s: 0.0,
+            delta_throttled_s: 0.0,
+            attributed_j: None,
+            delta_attributed_j: None,
         })
-        .collect()
+        .collect())
 }
 
-pub(crate) fn detect_cpu() -> Vec<RAPLZone> {
-    let mut zones = vec![];
-    for pkg in 0..cpu::topology().max_num_packages() {
-        let cpu_info = &cpu::topology().package(pkg).unwrap().cpu_info;
-        if !cpu_info.vendor.contains("GenuineIntel") {
-            eprintln!(
-                "Detected `{}` cpu, run-rapl only support Intel CPU.",
-                cpu_info.vendor,
-            );
-            std::process::exit(1);
-        }
+/// Discover the RAPL domains readable through `backend`.
+pub fn detect_zones(backend: Backend) -> Result<Vec<RAPLZone>, RError> {
+    probe_domains(open_source(backend)?.as_ref())
+}
+
+/// Probe every candidate RAPL domain of `backend`.
+///
+/// The power info of the package and DRAM zones is attached if it can be read.
+pub fn probe_zones(backend: Backend) -> Result<Vec<ZoneProbe>, RError> {
+    let mut probes = probe(open_source(backend)?.as_ref(), PROBE_WINDOW)?;
+    let power_info = read_all_power_info();
+    for probe in probes.iter_mut() {
+        probe.power_info = power_info.iter().find(|i| i.zone == probe.name).cloned();
+    }
 
-        let core = get_core_of_package(pkg).unwrap();
-
-        // Sapphire Rapids microarchitecture. It supports the follows MSRs:
-        if cpu_info.ext_family == 0x06 && cpu_info.ext_model == 0x8f {
-            // MSR_PP0_ENERGY_STATUS
-            // MSR_PP0_ENERGY_STATUS/MSR_PP1_ENERGY_STATUS are read-only MSRs. They report the actual energy use
-            // for the respective power plane domains. These MSRs are updated every ~1msec.
-            //
-            // Total Energy Consumed (31:0): The unsigned integer value represents the total amount of energy consumed since the last time this register was cleared.
-            // The unit of this field is specified by the “Energy Status Units” field of MSR_RAPL_POWER_UNIT.
-            zones.push(RAPLZone {
-                core: core,
-                name: format!("Pp0-{}", pkg),
-                which: 1593,
-                unit: read_rapl_energy_unit(pkg),
-            });
-
-            // MSR_DRAM_ENERGY_STATUS
-
-            // Energy in 61 micro-joules. Requires BIOS configuration to enable DRAM
-            // RAPL mode 0 (Direct VR).
-            zones.push(RAPLZone {
-                core: core,
-                name: format!("Dram-{}", pkg),
-                which: 1561,
-                unit: 0.000061,
-            });
-
-            // MSR_PKG_ENERGY_STATUS
-            //
-            // Total energy consumption in J (32.0), in 10nsec units.
-            zones.push(RAPLZone {
-                core: core,
-                name: format!("Package-{}", pkg),
-                which: 1553,
-                unit: read_rapl_energy_unit(pkg),
-            });
+    Ok(probes)
+}
+
+/// The power info of every package. Packages it cannot be read from, e.g.
+/// without MSR access or on AMD CPUs, are skipped.
+pub fn read_all_power_info() -> Vec<PowerInfo> {
+    let mut out = vec![];
+    for pkg in 0..cpu::topology().max_num_packages() {
+        match read_power_info(pkg) {
+            Ok(info) => out.extend(info),
+            Err(e) => {
+                crate::ignore_rerr!(e);
+            }
         }
     }
 
-    zones
+    out
 }
 
 pub(crate) fn update_measurements(
+    source: &dyn EnergySource,
     zones: &mut Vec<models::RAPLData>,
-    now: Instant,
     start_time: Instant,
-    prev_time: Instant,
-    output_file: &PathBuf,
+    start_wall_time: SystemTime,
     isolate_map: Option<&HashMap<String, models::IsolateData>>,
     smooth: bool,
-) {
+) -> Result<(), RError> {
     for zone in zones.iter_mut() {
-        match isolate_map {
-            Some(map) => {
-                let iz = map.get(&zone.zone.name).unwrap();
-                calculate_isolated_power_metrics(zone, now, start_time, prev_time, iz, smooth)
+        match isolate_map.and_then(|map| map.get(&zone.zone.name)) {
+            Some(iz) => {
+                calculate_isolated_power_metrics(
+                    source,
+                    zone,
+                    start_time,
+                    start_wall_time,
+                    iz,
+                    smooth,
+                )?
             }
-            _ => calculate_power_metrics(zone, now, start_time, prev_time, smooth),
+            None => calculate_power_metrics(source, zone, start_time, start_wall_time, smooth)?,
         }
-
-        crate::logger::log_poll_result(zone, output_file);
     }
-}
 
-fn read_rapl_energy_unit(pkg: u16) -> f64 {
-    const MSR_RAPL_POWER_UNIT: u32 = 1542;
-    const ENERGY_STATUS_UNIT_MASK: u64 = 0xF00;
-    const ENERGY_STATUS_UNIT_SHIFT: usize = 8;
-    let core = get_core_of_package(pkg).unwrap();
-    let rapl_power_unit = crate::cpuid::read_msr(core, MSR_RAPL_POWER_UNIT).unwrap();
-    let raw_energy_status_units =
-        (rapl_power_unit & ENERGY_STATUS_UNIT_MASK) >> ENERGY_STATUS_UNIT_SHIFT;
-    1.0 / (1 << raw_energy_status_units as usize) as f64
+    Ok(())
 }
 
-fn get_core_of_package(pkg: u16) -> Option<u32> {
+pub(crate) fn get_core_of_package(pkg: u16) -> Option<u32> {
     for (core, enable) in cpu::topology()
         .package(pkg)
         .unwrap()
@@ -159,63 +131,185 @@ fn get_core_of_package(pkg: u16) -> Option<u32> {
     None
 }
 
+pub(crate) fn cores_of_package(pkg: u16) -> Vec<u32> {
+    cpu::topology()
+        .package(pkg)
+        .map(|package| {
+            package
+                .lcores
+                .into_iter()
+                .enumerate()
+                .filter(|(_, enable)| *enable)
+                .map(|(core, _)| core as u32)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The enabled logical cores of every package.
+pub(crate) fn online_cpus() -> Vec<u32> {
+    (0..cpu::topology().max_num_packages())
+        .flat_map(cores_of_package)
+        .collect()
+}
+
+/// The first logical core of every physical core in package `pkg`.
+pub(crate) fn physical_cores_of_package(pkg: u16) -> Vec<u32> {
+    cores_of_package(pkg)
+        .into_iter()
+        .filter(|core| {
+            let siblings = std::fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                core
+            ))
+            .ok()
+            .and_then(|list| parse_cpu_list(&list).ok());
+            match siblings {
+                Some(siblings) => siblings.iter().min() == Some(core),
+                None => true,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn package_of_core(core: u32) -> Option<u16> {
+    (0..cpu::topology().max_num_packages()).find(|pkg| {
+        cpu::topology()
+            .package(*pkg)
+            .unwrap()
+            .lcores
+            .get(core as usize)
+            .cloned()
+            .unwrap_or(false)
+    })
+}
+
+/// Parse a cpu list such as `0-3,8,10-11`.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<u32>, RError> {
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|v| !v.is_empty()) {
+        let bounds: Vec<&str> = range.split('-').collect();
+        let parse = |v: &str| {
+            v.trim().parse::<u32>().map_err(|e| {
+                new_custom_msg(
+                    INVALID_VALUE,
+                    format!("Invalid cpu list `{}` (error: {})", list, e),
+                )
+            })
+        };
+        match bounds.len() {
+            1 => cpus.push(parse(bounds[0])?),
+            2 => cpus.extend(parse(bounds[0])?..=parse(bounds[1])?),
+            _ => {
+                crate::throw_rerr!(INVALID_VALUE, "Invalid cpu list `{}`", list);
+            }
+        }
+    }
+
+    Ok(cpus)
+}
+
+/// Parse a duration such as `500us`, `20ms`, `1.5s`, `2m` or `1h`.
+///
+/// A value without unit is read as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, RError> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: f64 = value.parse().map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!("Invalid duration `{}` (error: {})", s, e),
+        )
+    })?;
+
+    let secs = match unit.trim() {
+        "ns" => value / 1e9,
+        "us" | "µs" => value / 1e6,
+        "ms" => value / 1e3,
+        "" | "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        unit => {
+            crate::throw_rerr!(
+                INVALID_VALUE,
+                "Invalid duration `{}` (unknown unit `{}`)",
+                s,
+                unit
+            );
+        }
+    };
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
 pub(crate) fn calculate_isolated_power_metrics(
+    source: &dyn EnergySource,
     zone: &mut models::RAPLData,
-    now: Instant,
     start_time: Instant,
-    prev_time: Instant,
+    start_wall_time: SystemTime,
     isolated_zone: &models::IsolateData,
     smooth: bool,
-) {
-    calculate_power_metrics(zone, now, start_time, prev_time, smooth);
+) -> Result<(), RError> {
+    calculate_power_metrics(source, zone, start_time, start_wall_time, smooth)?;
     zone.total_power_j -= zone.delta_power_j;
     zone.delta_power_j -= isolated_zone.delta_power_j.avg;
     zone.total_power_j += zone.delta_power_j;
     zone.watt -= isolated_zone.watt.avg;
     zone.avg_watt -= isolated_zone.avg_watt.avg;
+
+    Ok(())
 }
 
 fn calculate_power_metrics(
+    source: &dyn EnergySource,
     zone: &mut models::RAPLData,
-    now: Instant,
     start_time: Instant,
-    prev_time: Instant,
+    start_wall_time: SystemTime,
     smooth: bool,
-) {
-    let cur_power = crate::cpuid::read_msr(zone.zone.core, zone.zone.which).unwrap();
-
-    #[allow(unused_assignments)]
-    let mut delta_power = 0;
-
-    // if RAPL overflow has occurred
-    //
-    // Assuming the energy unit is 1 micro-joule, the maximum value for each 32-bit
-    // sensor register is 4,294,967,295, which defines the boundary for upward overflow.
-    // According to Intel's user manual, these registers are updated more than 100 times
-    // per second. Therefore, when we read the register value every 10 milliseconds,
-    // a RAPL (Running Average Power Limit) cycle will never complete within a read interval,
-    // as doing so would require the computer to operate at a power level of 429,496 watts.
-    if zone.prev_power_read > cur_power {
-        delta_power = (u32::MAX as u64 - zone.prev_power_read) + cur_power;
-    } else {
-        delta_power = cur_power - zone.prev_power_read;
-    }
+) -> Result<(), RError> {
+    // Timestamp the counter with the middle of its read.
+    let before = Instant::now();
+    let cur_power = source.read(&zone.zone)?;
+    let after = Instant::now();
+    let now = before + after.duration_since(before) / 2;
+    let first = zone.read_time.is_none();
+    let prev_time = zone.read_time.unwrap_or(start_time);
+    let range = source.wrap_range(&zone.zone);
+    let unit = source.unit(&zone.zone);
+
+    let mut delta_power = counter_delta(zone.prev_power_read, cur_power, range);
 
     // Reset the delta_power consumption to zero during the initial measurement.
     // This ensures that the total energy consumption for each RAPL domain begins from zero.
-    if prev_time == start_time {
+    if first {
         delta_power = 0;
+        zone.gap = false;
+    } else {
+        let interval = now.duration_since(prev_time);
+        zone.gap = may_wrap_more_than_once(interval, range, unit, source.max_power(&zone.zone));
+    }
+    if zone.gap {
+        zone.gaps += 1;
     }
 
-    let delta_power_j = delta_power as f64 * zone.zone.unit;
+    let delta_power_j = delta_power as f64 * unit;
 
+    zone.total_raw = zone.total_raw.wrapping_add(delta_power);
     zone.total_power_j += delta_power_j;
     zone.delta_power_j = delta_power_j;
     zone.prev_power_read = cur_power;
 
     // Calculate the instantaneous power in Watt(J/s)
     let last_watt = zone.watt;
-    zone.watt = delta_power_j * 1000.0 / (now.duration_since(prev_time).as_millis() as f64);
+    let interval = now.duration_since(prev_time).as_secs_f64();
+    zone.watt = if interval > 0.0 {
+        delta_power_j / interval
+    } else {
+        0.0
+    };
     if smooth {
         if zone.watt > last_watt {
             zone.watt = last_watt + (zone.watt - last_watt) * 0.2;
@@ -224,38 +318,143 @@ fn calculate_power_metrics(
         }
     }
     // Calculate the average power in Watt(J/s) over the time from the beginning of the measurement to the present.
-    zone.avg_watt =
-        zone.total_power_j * 1000.0 / (now.duration_since(start_time).as_millis() as f64);
+    let elapsed = now.duration_since(start_time);
+    zone.avg_watt = if elapsed.is_zero() {
+        0.0
+    } else {
+        zone.total_power_j / elapsed.as_secs_f64()
+    };
 
-    zone.time_elapsed = now.duration_since(start_time).as_secs();
+    zone.time_elapsed = elapsed.as_secs_f64();
+    // Derived from the monotonic clock, so that the timestamps do not jump with the wall clock.
+    zone.timestamp_ns = (start_wall_time + elapsed)
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Failed to check duration")
+        .as_nanos() as u64;
+    zone.read_time = Some(now);
+    zone.read_latency = after.duration_since(before);
+
+    calculate_throttle_metrics(source, zone, first)
 }
 
-pub(crate) fn read_isolated_data(
+fn calculate_throttle_metrics(
+    source: &dyn EnergySource,
+    zone: &mut models::RAPLData,
+    first: bool,
+) -> Result<(), RError> {
+    let throttle = match zone.zone.throttle {
+        Some(throttle) => throttle,
+        None => return Ok(()),
+    };
+    let cur_throttle = match source.read_throttle(&zone.zone)? {
+        Some(cur_throttle) => cur_throttle,
+        None => return Ok(()),
+    };
+
+    // The perf status counters are 32-bit wide and wrap like the energy counters.
+    let mut delta_throttle =
+        counter_delta(zone.prev_throttle_read, cur_throttle, throttle.range);
+    if first {
+        delta_throttle = 0;
+    }
+
+    zone.delta_throttled_s = delta_throttle as f64 * throttle.unit;
+    zone.throttled_s += zone.delta_throttled_s;
+    zone.prev_throttle_read = cur_throttle;
+
+    Ok(())
+}
+
+/// Increment of a counter from `prev` to `cur`, where `range` is the largest
+/// value of the counter before it wraps around to zero.
+///
+/// At most one wraparound is accounted for, see [`may_wrap_more_than_once`].
+pub(crate) fn counter_delta(prev: u64, cur: u64, range: u64) -> u64 {
+    if prev > cur {
+        // The counter went through `range` and zero: (range - prev) + 1 + cur.
+        // For a 64-bit counter, this wraps around to the same value.
+        (range - prev).wrapping_add(cur).wrapping_add(1)
+    } else {
+        cur - prev
+    }
+}
+
+/// Whether a counter of `range` and `unit` may wrap around more than once
+/// within `interval` at `max_power_w`, which makes [`counter_delta`] ambiguous.
+///
+/// E.g. the 32-bit MSR counters with the common 61 µJ unit wrap after about
+/// 262 kJ, that is every 17 minutes at 250 W.
+pub(crate) fn may_wrap_more_than_once(
+    interval: Duration,
+    range: u64,
+    unit: f64,
+    max_power_w: f64,
+) -> bool {
+    if max_power_w <= 0.0 {
+        return false;
+    }
+
+    let wrap_period_s = (range as f64 + 1.0) * unit / max_power_w;
+    interval.as_secs_f64() >= wrap_period_s
+}
+
+/// Load isolation data previously generated by `isolate`.
+pub fn read_isolated_data(
     isolate_file: Option<&PathBuf>,
-) -> Option<HashMap<String, IsolateData>> {
-    isolate_file.map(|file_path| {
-        let data = std::fs::read(file_path).expect("Couldn't read file");
-        let map: HashMap<String, models::IsolateData> =
-            serde_json::from_str(String::from_utf8(data).unwrap().as_str()).unwrap();
-        map
-    })
+) -> Result<Option<HashMap<String, IsolateData>>, RError> {
+    let file_path = match isolate_file {
+        Some(file_path) => file_path,
+        None => return Ok(None),
+    };
+
+    let data = std::fs::read_to_string(file_path).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to read {} (error: {})", file_path.display(), e),
+        )
+    })?;
+    let map = serde_json::from_str(&data).map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!(
+                "Failed to parse isolate data {} (error: {})",
+                file_path.display(),
+                e
+            ),
+        )
+    })?;
+
+    Ok(Some(map))
 }
 
-pub(crate) fn get_last_measurement_from(file: PathBuf) -> Vec<models::RAPLData> {
-    let mut rdr = csv::Reader::from_path(file).unwrap();
-    let zones = detect_cpu();
+/// Read the last sample of every zone recorded in a measurement .csv file.
+pub fn read_last_measurement(file: &PathBuf) -> Result<Vec<models::RAPLData>, RError> {
+    let mut rdr = csv::Reader::from_path(file).map_err(|e| {
+        new_custom_msg(
+            NOT_ALLOWED,
+            format!("Failed to open {} (error: {})", file.to_str().unwrap(), e),
+        )
+    })?;
+
     let mut out: Vec<models::RAPLData> = vec![];
     for res in rdr.deserialize() {
-        let r: models::RAPLData = res.unwrap();
-        out.push(r);
+        let r: models::RAPLData = res.map_err(|e| {
+            new_custom_msg(
+                INVALID_VALUE,
+                format!("Failed to deserialize RAPLData (error:{})", e),
+            )
+        })?;
+
+        match out.iter_mut().find(|d| d.zone_name == r.zone_name) {
+            Some(last) => *last = r,
+            None => out.push(r),
+        }
     }
 
-    let last = &out[out.len() - zones.len()..].to_vec();
-
-    return last.to_owned();
+    Ok(out)
 }
 
-pub(crate) fn create_log_file_name<S: AsRef<str>, T: AsRef<str>>(
+pub fn create_log_file_name<S: AsRef<str>, T: AsRef<str>>(
     // dir: Option<&PathBuf>,
     benchmark_name: S,
     tool: T,
@@ -273,104 +472,132 @@ pub(crate) fn create_log_file_name<S: AsRef<str>, T: AsRef<str>>(
     }
 }
 
-pub(crate) fn spacing<S: AsRef<str>>(line: S) -> String {
-    // 25 and 30 makes for fucky formatting:
-    // w/h is misaligned; line break on small monitors, respectively
-    let col_spacing = 28;
-    return " ".repeat(col_spacing - line.as_ref().len());
-}
-
-pub(crate) fn print_headers(ncurses: bool) {
-    let headers = vec![
-        "zone", "time(s)", "J", // total energy consumed
-        "delta_J", "avg J/s", "J/s",
-    ];
-    let mut line: String = "".to_owned();
+/// Summarize a run that started at `start_time` and took `duration`, from the
+/// last samples of its zones.
+pub fn create_run_summary<S: AsRef<str>, T: AsRef<str>>(
+    name: S,
+    tool: T,
+    backend: Backend,
+    start_time: SystemTime,
+    duration: Duration,
+    zones: &[models::RAPLData],
+) -> models::RunSummary {
+    let power_info = read_all_power_info();
+    let zones = zones
+        .iter()
+        .map(|data| {
+            let mut summary = models::ZoneSummary::from(data);
+            summary.avg_tdp_percent = power_info
+                .iter()
+                .find(|i| i.zone == data.zone_name && i.thermal_spec_w > 0.0)
+                .map(|i| data.avg_watt * 100.0 / i.thermal_spec_w);
+            summary
+        })
+        .collect();
 
-    for h in headers {
-        line.push_str(format!("{}{}", h, spacing(h.to_string())).as_str());
+    models::RunSummary {
+        name: name.as_ref().to_string(),
+        tool: tool.as_ref().to_string(),
+        backend,
+        start_time: start_time
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Failed to check duration")
+            .as_secs(),
+        duration_s: duration.as_secs_f64(),
+        status: models::RunStatus::Completed,
+        caps: vec![],
+        interval: None,
+        sampler: None,
+        attribution: None,
+        exit: None,
+        placement: None,
+        power_info,
+        zones,
     }
+}
 
-    line = line.trim().to_string();
-    line.push_str("\n");
-    if ncurses {
-        ncurses::attron(ncurses::A_BOLD());
-        ncurses::addstr(line.as_str());
-        ncurses::attroff(ncurses::A_BOLD());
-        ncurses::refresh();
-    } else {
-        print!("{}", line);
+/// Summarize a batch of `iterations`. The statistics leave out the warm-up
+/// iterations.
+pub fn create_batch_summary<S: AsRef<str>, T: AsRef<str>>(
+    name: S,
+    tool: T,
+    iterations: Vec<models::IterationSummary>,
+) -> models::BatchSummary {
+    let measured: Vec<&models::IterationSummary> =
+        iterations.iter().filter(|i| !i.warmup).collect();
+    let zone_names: Vec<String> = measured
+        .first()
+        .map(|i| i.zones.iter().map(|z| z.zone_name.clone()).collect())
+        .unwrap_or_default();
+
+    let zones = zone_names
+        .into_iter()
+        .map(|zone_name| {
+            let samples: Vec<&models::ZoneSummary> = measured
+                .iter()
+                .filter_map(|i| i.zones.iter().find(|z| z.zone_name == zone_name))
+                .collect();
+            let attributed: Vec<f64> = samples.iter().filter_map(|z| z.attributed_j).collect();
+
+            models::ZoneStats {
+                zone_name,
+                energy_j: Stats::from_samples(
+                    &samples
+                        .iter()
+                        .map(|z| z.total_power_j)
+                        .collect::<Vec<f64>>(),
+                ),
+                avg_watt: Stats::from_samples(
+                    &samples.iter().map(|z| z.avg_watt).collect::<Vec<f64>>(),
+                ),
+                attributed_j: if attributed.is_empty() {
+                    None
+                } else {
+                    Some(Stats::from_samples(&attributed))
+                },
+            }
+        })
+        .collect();
+
+    models::BatchSummary {
+        name: name.as_ref().to_string(),
+        tool: tool.as_ref().to_string(),
+        warmup: iterations.iter().filter(|i| i.warmup).count() as u32,
+        duration_s: Stats::from_samples(
+            &measured.iter().map(|i| i.duration_s).collect::<Vec<f64>>(),
+        ),
+        zones,
+        iterations,
     }
 }
 
-#[macro_export]
-macro_rules! print_headers {
-    ($ncurses: expr) => {
-        // what the fuck
-        {
-            crate::common::print_headers($ncurses);
-        }
-    };
-    () => {
-        // what the fuck
-        {
-            crate::common::print_headers(false);
-        }
-    };
+/// The run summary written next to the .csv output `output_file`.
+pub fn create_summary_file_name(output_file: &Path) -> PathBuf {
+    output_file.with_extension("summary.json")
 }
 
-pub(crate) fn print_result_line(zones: &Vec<models::RAPLData>, ncurses: bool) {
-    let mut line: String = "\r".repeat(zones.len()).to_owned();
-
-    for zone in zones {
-        let fields = vec![
-            zone.time_elapsed as f64,
-            zone.total_power_j,
-            zone.delta_power_j,
-            zone.avg_watt,
-            zone.watt,
-        ];
-        let zone_name = zone.zone_name.as_str();
-        line.push_str(format!("{}{}", zone_name, spacing(zone_name)).as_str());
-
-        for f in fields {
-            line.push_str(format!("{:.5}{}", f, spacing(format!("{:.5}", f))).as_str());
-        }
-
-        line = line.trim().to_string();
-
-        line.push_str("\n");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_wraparound() {
+        let range = u32::MAX as u64;
+        assert_eq!(counter_delta(10, 25, range), 15);
+        assert_eq!(counter_delta(range, 0, range), 1);
+        assert_eq!(counter_delta(range - 4, 5, range), 10);
+        assert_eq!(counter_delta(u64::MAX - 1, 3, u64::MAX), 5);
+        // powercap ranges are not a power of two
+        assert_eq!(counter_delta(262143328849, 1, 262143328850), 3);
     }
 
-    if ncurses {
-        print_headers!(true);
-        ncurses::addstr(line.as_str());
-        ncurses::refresh();
-    } else {
-        print!("{}", line);
+    #[test]
+    fn multiple_wraps_possible() {
+        let range = u32::MAX as u64;
+        let unit = 1.0 / (1u64 << 14) as f64;
+        // 2^32 * 2^-14 J = 262144 J, which takes 1048.576 s at 250 W.
+        assert!(!may_wrap_more_than_once(Duration::from_secs(1000), range, unit, 250.0));
+        assert!(may_wrap_more_than_once(Duration::from_secs(1049), range, unit, 250.0));
+        assert!(!may_wrap_more_than_once(Duration::from_secs(3600), u64::MAX, 1e-9, 1000.0));
     }
 }
-
-#[macro_export]
-macro_rules! ncprint {
-    ($str: expr) => {
-        ncurses::addstr($str);
-        ncurses::refresh();
-    };
-}
-
-#[macro_export]
-macro_rules! print_result_line {
-    ($zones: expr, $ncurses: expr) => {
-        // what the fuck
-        {
-            crate::common::print_result_line($zones, $ncurses);
-        }
-    };
-    ($zones: expr) => {
-        // what the fuck
-        {
-            crate::common::print_result_line($zones, false);
-        }
-    };
-}
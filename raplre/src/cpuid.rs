@@ -111,3 +111,26 @@ pub(crate) fn read_msr(core: u32, which: u32) -> Result<u64, RError> {
         res
     }
 }
+
+/// Write `value` to the msr `which` of `core` through `/dev/cpu/<core>/msr`.
+///
+/// libcpuid opens the msr device read-only, so writes bypass its driver.
+pub(crate) fn write_msr(core: u32, which: u32, value: u64) -> Result<(), RError> {
+    use std::os::unix::fs::FileExt;
+
+    let path = format!("/dev/cpu/{}/msr", core);
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .map_err(|e| {
+            crate::new_custom_msg(IOERR, format!("Failed to open {} (error: {})", path, e))
+        })?;
+
+    file.write_all_at(&value.to_ne_bytes(), which as u64)
+        .map_err(|e| {
+            crate::new_custom_msg(
+                IOERR,
+                format!("Failed to write msr at {} (error: {})", which, e),
+            )
+        })
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::arch::{detect_vendor, Vendor};
+use crate::common::{get_core_of_package, physical_cores_of_package};
+use crate::error::*;
+use crate::freq::IA32_TIME_STAMP_COUNTER;
+
+/// Package C-states with a residency counter, and the MSR of the counter.
+pub const PKG_CSTATES: [(&str, u32); 7] = [
+    ("pc2", 0x60D),
+    ("pc3", 0x3F8),
+    ("pc6", 0x3F9),
+    ("pc7", 0x3FA),
+    ("pc8", 0x630),
+    ("pc9", 0x631),
+    ("pc10", 0x632),
+];
+pub(crate) const MSR_CORE_C6_RESIDENCY: u32 = 0x3FD;
+
+/// The C-state residency of a package over a sample interval, in percent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ResidencySample {
+    /// Residency of the package C-states, in the order of [`PKG_CSTATES`].
+    /// States the package does not report are `None`.
+    pub package: [Option<f64>; 7],
+    /// Average core C6 residency of the physical cores of the package.
+    pub core_c6: Option<f64>,
+}
+
+/// The C-state residency counters of a package.
+///
+/// The residency counters count at the TSC frequency while the package (or
+/// the core) is in the C-state, so the residency over an interval is
+/// `Δcounter / ΔTSC`.
+#[derive(Debug, Clone)]
+pub struct PackageCStates {
+    pub pkg: u16,
+    core: u32,
+    // index into PKG_CSTATES of the counters the package implements
+    states: Vec<usize>,
+    cores: Vec<u32>,
+    prev: Vec<u64>,
+}
+
+impl PackageCStates {
+    /// Open the residency counters of package `pkg`.
+    ///
+    /// Which package C-states are reported depends on the model; counters that
+    /// cannot be read are left out.
+    pub fn open(pkg: u16) -> Result<Self, RError> {
+        if detect_vendor(pkg)? != Vendor::Intel {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "C-state residency counters are only supported on Intel CPUs"
+            );
+        }
+
+        let core = match get_core_of_package(pkg) {
+            Some(core) => core,
+            None => {
+                crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+            }
+        };
+
+        let mut states = vec![];
+        for (idx, (_, which)) in PKG_CSTATES.iter().enumerate() {
+            match crate::cpuid::read_msr(core, *which) {
+                Ok(_) => states.push(idx),
+                Err(e) => {
+                    crate::ignore_rerr!(e);
+                }
+            }
+        }
+
+        let cores = match crate::cpuid::read_msr(core, MSR_CORE_C6_RESIDENCY) {
+            Ok(_) => physical_cores_of_package(pkg),
+            Err(e) => {
+                crate::ignore_rerr!(e);
+                vec![]
+            }
+        };
+
+        if states.is_empty() && cores.is_empty() {
+            crate::throw_rerr!(
+                NOT_FOUND,
+                "No C-state residency counter in package {}",
+                pkg
+            );
+        }
+
+        let mut cstates = Self {
+            pkg,
+            core,
+            states,
+            cores,
+            prev: vec![],
+        };
+        cstates.prev = cstates.read_counters()?;
+
+        Ok(cstates)
+    }
+
+    /// The residency since the previous call, or since [`PackageCStates::open`].
+    pub fn sample(&mut self) -> Result<ResidencySample, RError> {
+        let cur = self.read_counters()?;
+        let delta: Vec<u64> = cur
+            .iter()
+            .zip(self.prev.iter())
+            .map(|(cur, prev)| cur.wrapping_sub(*prev))
+            .collect();
+        self.prev = cur;
+
+        Ok(residency(&self.states, &delta))
+    }
+
+    fn read_counters(&self) -> Result<Vec<u64>, RError> {
+        let mut out = vec![crate::cpuid::read_msr(self.core, IA32_TIME_STAMP_COUNTER)?];
+        for idx in self.states.iter() {
+            out.push(crate::cpuid::read_msr(self.core, PKG_CSTATES[*idx].1)?);
+        }
+        for core in self.cores.iter() {
+            out.push(crate::cpuid::read_msr(*core, IA32_TIME_STAMP_COUNTER)?);
+            out.push(crate::cpuid::read_msr(*core, MSR_CORE_C6_RESIDENCY)?);
+        }
+
+        Ok(out)
+    }
+}
+
+/// The residency from the counter increments `delta` of an interval, laid out
+/// as `[tsc, package states..., (core tsc, core c6)...]`, `states` being the
+/// index into [`PKG_CSTATES`] of every package state.
+fn residency(states: &[usize], delta: &[u64]) -> ResidencySample {
+    let percent = |residency: u64, tsc: u64| match tsc {
+        0 => 0.0,
+        tsc => (residency as f64 * 100.0 / tsc as f64).min(100.0),
+    };
+
+    let mut sample = ResidencySample::default();
+    let tsc = delta[0];
+    for (pos, idx) in states.iter().enumerate() {
+        sample.package[*idx] = Some(percent(delta[1 + pos], tsc));
+    }
+
+    let cores = &delta[1 + states.len()..];
+    if !cores.is_empty() {
+        let (mut core_tsc, mut core_c6) = (0u64, 0u64);
+        for pair in cores.chunks(2) {
+            core_tsc += pair[0];
+            core_c6 += pair[1];
+        }
+        sample.core_c6 = Some(percent(core_c6, core_tsc));
+    }
+
+    sample
+}
+
+/// Open the residency counters of every package. Packages without readable
+/// counters, e.g. without MSR access or on AMD CPUs, are skipped.
+pub(crate) fn open_cstates() -> Vec<PackageCStates> {
+    let mut out = vec![];
+    for pkg in 0..cpu::topology().max_num_packages() {
+        match PackageCStates::open(pkg) {
+            Ok(cstates) => out.push(cstates),
+            Err(e) => {
+                crate::ignore_rerr!(e);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn residency_percent() {
+        // pc2 and pc6, and two cores.
+        let delta = [1000, 250, 500, 1000, 900, 1000, 700];
+        let sample = residency(&[0, 2], &delta);
+        assert_eq!(
+            sample.package,
+            [Some(25.0), None, Some(50.0), None, None, None, None]
+        );
+        assert_eq!(sample.core_c6, Some(80.0));
+
+        // Counters that advanced more than the TSC are capped.
+        assert_eq!(residency(&[6], &[100, 101]).package[6], Some(100.0));
+
+        // An interval without TSC increment, and no core counters.
+        let sample = residency(&[0], &[0, 10]);
+        assert_eq!(sample.package[0], Some(0.0));
+        assert_eq!(sample.core_c6, None);
+    }
+}
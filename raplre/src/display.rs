@@ -0,0 +1,143 @@
+use std::time::Instant;
+
+use raplre::models;
+
+// ncurses
+const COLOUR_BLACK: i16 = 0;
+const DEFAULT_COLOUR: i16 = -1;
+pub(crate) const HEADER_PAIR: i16 = 1;
+pub(crate) const KEY_CODE_EXIT: i32 = 113; // q
+
+pub(crate) fn setup_ncurses() {
+    let w = ncurses::initscr();
+    ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+    ncurses::nodelay(w, true);
+
+    if ncurses::has_colors() {
+        ncurses::start_color();
+        ncurses::init_pair(HEADER_PAIR, COLOUR_BLACK, DEFAULT_COLOUR);
+    }
+}
+
+pub(crate) fn kill_ncurses() {
+    ncurses::endwin();
+    ncurses::reset_shell_mode();
+}
+
+pub(crate) fn should_terminate(limit: u64, now: Instant, start_time: Instant) -> bool {
+    return limit > 0 && now.duration_since(start_time).as_secs() >= limit;
+}
+
+pub(crate) fn terminate() {
+    kill_ncurses();
+}
+
+pub(crate) fn spacing<S: AsRef<str>>(line: S) -> String {
+    // 25 and 30 makes for fucky formatting:
+    // w/h is misaligned; line break on small monitors, respectively
+    let col_spacing = 28;
+    return " ".repeat(col_spacing - line.as_ref().len());
+}
+
+pub(crate) fn print_headers(ncurses: bool) {
+    let headers = vec![
+        "zone", "time(s)", "J", // total energy consumed
+        "delta_J", "avg J/s", "J/s", "temp(C)",
+    ];
+    let mut line: String = "".to_owned();
+
+    for h in headers {
+        line.push_str(format!("{}{}", h, spacing(h.to_string())).as_str());
+    }
+
+    line = line.trim().to_string();
+    line.push_str("\n");
+    if ncurses {
+        ncurses::attron(ncurses::A_BOLD());
+        ncurses::addstr(line.as_str());
+        ncurses::attroff(ncurses::A_BOLD());
+        ncurses::refresh();
+    } else {
+        print!("{}", line);
+    }
+}
+
+#[macro_export]
+macro_rules! print_headers {
+    ($ncurses: expr) => {
+        // what the fuck
+        {
+            crate::display::print_headers($ncurses);
+        }
+    };
+    () => {
+        // what the fuck
+        {
+            crate::display::print_headers(false);
+        }
+    };
+}
+
+pub(crate) fn print_result_line(zones: &[models::RAPLData], ncurses: bool) {
+    let mut line: String = "\r".repeat(zones.len()).to_owned();
+
+    for zone in zones {
+        let fields = vec![
+            zone.time_elapsed,
+            zone.total_power_j,
+            zone.delta_power_j,
+            zone.avg_watt,
+            zone.watt,
+        ];
+        let zone_name = zone.zone_name.as_str();
+        line.push_str(format!("{}{}", zone_name, spacing(zone_name)).as_str());
+
+        for f in fields {
+            line.push_str(format!("{:.5}{}", f, spacing(format!("{:.5}", f))).as_str());
+        }
+
+        // package temperature, followed by the hottest core if per-core temperatures are read
+        let temp = match (zone.temp, zone.core_temp_max) {
+            (Some(temp), Some(core_max)) => format!("{:.0} ({:.0})", temp, core_max),
+            (Some(temp), None) => format!("{:.0}", temp),
+            _ => "-".to_string(),
+        };
+        line.push_str(temp.as_str());
+
+        line = line.trim().to_string();
+
+        line.push_str("\n");
+    }
+
+    if ncurses {
+        print_headers!(true);
+        ncurses::addstr(line.as_str());
+        ncurses::refresh();
+    } else {
+        print!("{}", line);
+    }
+}
+
+#[macro_export]
+macro_rules! ncprint {
+    ($str: expr) => {
+        ncurses::addstr($str);
+        ncurses::refresh();
+    };
+}
+
+#[macro_export]
+macro_rules! print_result_line {
+    ($zones: expr, $ncurses: expr) => {
+        // what the fuck
+        {
+            crate::display::print_result_line($zones, $ncurses);
+        }
+    };
+    ($zones: expr) => {
+        // what the fuck
+        {
+            crate::display::print_result_line($zones, false);
+        }
+    };
+}
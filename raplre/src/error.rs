@@ -8,15 +8,15 @@ mod internel {
     static _RMODULE: RModule = RModule::new("raplre");
 
     #[inline]
-    pub(crate) fn new_simple(kind: RErrorKind) -> RError {
+    pub fn new_simple(kind: RErrorKind) -> RError {
         RError::new_simple(_RMODULE.clone(), kind)
     }
     #[inline]
-    pub(crate) fn new_simple_msg(kind: RErrorKind, msg: &'static str) -> RError {
+    pub fn new_simple_msg(kind: RErrorKind, msg: &'static str) -> RError {
         RError::new_simple_msg(_RMODULE.clone(), kind, msg)
     }
     #[inline]
-    pub(crate) fn new_custom_msg(kind: RErrorKind, msg: String) -> RError {
+    pub fn new_custom_msg(kind: RErrorKind, msg: String) -> RError {
         RError::new_custom_msg(_RMODULE.clone(), kind, msg)
     }
 }
@@ -31,13 +31,13 @@ pub use errore::error::RError;
 #[macro_export]
 macro_rules! throw_rerr {
         ($kind:expr) => {
-            return crate::new_simple($kind).to_err();
+            return $crate::new_simple($kind).to_err();
         };
         ($kind:expr,$msg:expr) => {
-            return crate::new_simple_msg($kind, $msg).to_err();
+            return $crate::new_simple_msg($kind, $msg).to_err();
         };
         ($kind:expr,$($args:tt)*) => {
-            return crate::new_custom_msg($kind,format!($($args)*)).to_err();
+            return $crate::new_custom_msg($kind,format!($($args)*)).to_err();
         }
     }
 
@@ -62,13 +62,13 @@ macro_rules! block_rerr {
 #[macro_export]
 macro_rules! new_rerr {
     ($kind:expr) => {
-            crate::new_simple($kind)
+            $crate::new_simple($kind)
         };
         ($kind:expr,$msg:expr) => {
-            crate::new_simple_msg($kind, $msg)
+            $crate::new_simple_msg($kind, $msg)
         };
         ($kind:expr,$($args:tt)*) => {
-            crate::new_custom_msg($kind,format!($($args)*))
+            $crate::new_custom_msg($kind,format!($($args)*))
         };
 }
     
\ No newline at end of file
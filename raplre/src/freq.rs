@@ -0,0 +1,155 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::cores_of_package;
+use crate::error::*;
+
+pub(crate) const IA32_TIME_STAMP_COUNTER: u32 = 0x10;
+pub(crate) const IA32_MPERF: u32 = 0xE7;
+pub(crate) const IA32_APERF: u32 = 0xE8;
+
+/// The effective frequency of a package over a sample interval.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrequencySample {
+    /// Average frequency of the cores while they were busy, in MHz.
+    pub freq_mhz: f64,
+    /// Share of the interval the cores were busy (in C0), in percent.
+    pub busy_percent: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CoreCounters {
+    tsc: u64,
+    mperf: u64,
+    aperf: u64,
+}
+
+/// The APERF/MPERF counters of every core of a package.
+///
+/// MPERF counts at the TSC frequency while the core is in C0, APERF at the
+/// actual frequency of the core. Over an interval, `ΔAPERF / ΔMPERF` is the
+/// ratio of the effective to the TSC frequency, and `ΔMPERF / ΔTSC` the share
+/// of time the core was busy.
+#[derive(Debug, Clone)]
+pub struct PackageFrequency {
+    pub pkg: u16,
+    cores: Vec<u32>,
+    prev: Vec<CoreCounters>,
+    prev_time: Instant,
+}
+
+impl PackageFrequency {
+    pub fn open(pkg: u16) -> Result<Self, RError> {
+        let cores = cores_of_package(pkg);
+        if cores.is_empty() {
+            crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+        }
+
+        let prev_time = Instant::now();
+        let prev = read_counters(&cores)?;
+        Ok(Self {
+            pkg,
+            cores,
+            prev,
+            prev_time,
+        })
+    }
+
+    /// The frequency of the package since the previous call, or since [`PackageFrequency::open`].
+    pub fn sample(&mut self) -> Result<FrequencySample, RError> {
+        let now = Instant::now();
+        let cur = read_counters(&self.cores)?;
+        let elapsed = now.duration_since(self.prev_time).as_secs_f64();
+
+        let sample = frequency(&self.prev, &cur, elapsed);
+        self.prev = cur;
+        self.prev_time = now;
+
+        Ok(sample)
+    }
+}
+
+/// The frequency of the cores from their counters `prev` and `cur`, read
+/// `elapsed` seconds apart. Zero if the interval is empty.
+fn frequency(prev: &[CoreCounters], cur: &[CoreCounters], elapsed: f64) -> FrequencySample {
+    let (mut tsc, mut mperf, mut aperf) = (0u64, 0u64, 0u64);
+    for (prev, cur) in prev.iter().zip(cur.iter()) {
+        // The counters are 64-bit wide and do not wrap in practice.
+        tsc += cur.tsc.wrapping_sub(prev.tsc);
+        mperf += cur.mperf.wrapping_sub(prev.mperf);
+        aperf += cur.aperf.wrapping_sub(prev.aperf);
+    }
+
+    if tsc == 0 || mperf == 0 || elapsed == 0.0 {
+        return FrequencySample {
+            freq_mhz: 0.0,
+            busy_percent: 0.0,
+        };
+    }
+
+    let tsc_hz = tsc as f64 / cur.len() as f64 / elapsed;
+    FrequencySample {
+        freq_mhz: tsc_hz * aperf as f64 / mperf as f64 / 1e6,
+        busy_percent: mperf as f64 * 100.0 / tsc as f64,
+    }
+}
+
+fn read_counters(cores: &[u32]) -> Result<Vec<CoreCounters>, RError> {
+    let mut out = Vec::with_capacity(cores.len());
+    for core in cores {
+        out.push(CoreCounters {
+            tsc: crate::cpuid::read_msr(*core, IA32_TIME_STAMP_COUNTER)?,
+            mperf: crate::cpuid::read_msr(*core, IA32_MPERF)?,
+            aperf: crate::cpuid::read_msr(*core, IA32_APERF)?,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Open the APERF/MPERF counters of every package.
+pub(crate) fn open_frequency() -> Result<Vec<PackageFrequency>, RError> {
+    (0..cpu::topology().max_num_packages())
+        .map(PackageFrequency::open)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(tsc: u64, mperf: u64, aperf: u64) -> CoreCounters {
+        CoreCounters { tsc, mperf, aperf }
+    }
+
+    #[test]
+    fn frequency_from_counters() {
+        // Two cores with a 2 GHz TSC over 0.5 s: one busy half of the time at
+        // 3 GHz, the other busy all the time at 2 GHz.
+        let prev = [counters(1000, 100, 200), counters(1000, 100, 200)];
+        let cur = [
+            counters(1_000_001_000, 500_000_100, 750_000_200),
+            counters(1_000_001_000, 1_000_000_100, 1_000_000_200),
+        ];
+        let sample = frequency(&prev, &cur, 0.5);
+        assert_eq!(sample.busy_percent, 75.0);
+        assert!((sample.freq_mhz - 2333.333).abs() < 1e-3);
+
+        // The counters wrapped around: 200 TSC ticks in 100 ns, half of them busy
+        // at the TSC frequency.
+        let prev = [counters(u64::MAX - 99, u64::MAX - 49, u64::MAX - 49)];
+        let cur = [counters(100, 50, 50)];
+        let sample = frequency(&prev, &cur, 100.0 / 1e9);
+        assert_eq!(sample.busy_percent, 50.0);
+        assert!((sample.freq_mhz - 2000.0).abs() < 1e-6);
+
+        // No time elapsed, or no TSC increment.
+        let empty = FrequencySample {
+            freq_mhz: 0.0,
+            busy_percent: 0.0,
+        };
+        assert_eq!(frequency(&prev, &prev, 0.5), empty);
+        assert_eq!(frequency(&prev, &cur, 0.0), empty);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use crate::error::*;
+use crate::models::{self, IsolateData, StatData};
+
+/// Generate isolation data from the samples of a measurement .csv file.
+pub fn isolate_data_from_csv(csv_file: &PathBuf) -> Result<HashMap<String, IsolateData>, RError> {
+    if !csv_file.exists() {
+        crate::throw_rerr!(
+            NOT_FOUND,
+            "Failed to open {} (error: not found)",
+            csv_file.to_str().unwrap()
+        );
+    }
+
+    if !csv_file.is_file() {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "Failed to open {} (error: not a file)",
+            csv_file.to_str().unwrap()
+        );
+    }
+
+    let mut rdr = csv::Reader::from_path(csv_file).map_err(|e| {
+        new_custom_msg(
+            NOT_ALLOWED,
+            format!(
+                "Failed to open {} (error:{})",
+                csv_file.to_str().unwrap(),
+                e
+            ),
+        )
+    })?;
+
+    let mut map: HashMap<String, Vec<models::RAPLData>> = HashMap::new();
+    let mut out_map = HashMap::new();
+
+    for res in rdr.deserialize() {
+        let r: models::RAPLData = res.map_err(|e| {
+            new_custom_msg(
+                INVALID_VALUE,
+                format!("Failed to deserialize RAPLData (error:{})", e),
+            )
+        })?;
+
+        map.entry(r.zone_name.clone()).or_insert(vec![]).push(r);
+    }
+
+    for (zone_name, mut zone_data) in map {
+        // The first sample of each zone always reports zero consumption.
+        zone_data.remove(0);
+        let data_len = zone_data.len();
+        if data_len < 2 {
+            crate::throw_rerr!(
+                INVALID_VALUE,
+                "Not enough samples of `{}` in {}",
+                zone_name,
+                csv_file.to_str().unwrap()
+            );
+        }
+
+        let residency = residency_stat_data(&zone_data);
+
+        let mut delta_power_j_step = vec![];
+        let mut watts_step = vec![];
+        let mut avg_watts = vec![];
+
+        for data in &zone_data[..data_len - 1] {
+            delta_power_j_step.push(data.delta_power_j);
+            watts_step.push(data.watt);
+            avg_watts.push(data.avg_watt);
+        }
+
+        out_map.insert(
+            zone_name,
+            IsolateData {
+                delta_power_j: stat_data(&delta_power_j_step, data_len),
+                watt: stat_data(&watts_step, data_len),
+                avg_watt: stat_data(&avg_watts, data_len),
+                residency,
+            },
+        );
+    }
+
+    Ok(out_map)
+}
+
+fn residency_stat_data(zone_data: &[models::RAPLData]) -> BTreeMap<String, StatData> {
+    let mut steps: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for data in zone_data {
+        let states = [
+            ("pc2", data.pc2_percent),
+            ("pc3", data.pc3_percent),
+            ("pc6", data.pc6_percent),
+            ("pc7", data.pc7_percent),
+            ("pc8", data.pc8_percent),
+            ("pc9", data.pc9_percent),
+            ("pc10", data.pc10_percent),
+            ("core_c6", data.core_c6_percent),
+        ];
+        for (state, value) in states {
+            if let Some(value) = value {
+                steps.entry(state.to_string()).or_default().push(value);
+            }
+        }
+    }
+
+    steps
+        .into_iter()
+        .map(|(state, steps)| {
+            let len = steps.len();
+            (state, stat_data(&steps, len))
+        })
+        .collect()
+}
+
+fn stat_data(steps: &[f64], data_len: usize) -> StatData {
+    StatData {
+        min: steps.iter().cloned().fold(f64::NAN, f64::min),
+        max: steps.iter().cloned().fold(f64::NAN, f64::max),
+        avg: steps.iter().sum::<f64>() / data_len as f64,
+        total: steps.iter().sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn residency_summary() {
+        let csv_file =
+            std::env::temp_dir().join(format!("raplre-isolate-{}.csv", std::process::id()));
+        std::fs::write(
+            &csv_file,
+            "zone_name,time_elapsed,prev_power_read,total_power_j,delta_power_j,watt,avg_watt,pc2_percent,pc6_percent,core_c6_percent
+Package-0,0,0,0,0,0,0,,,
+Package-0,1,0,2,2,2,2,10,50,80
+Package-0,2,0,4,2,2,2,20,,90
+Package-0,3,0,6,2,2,2,30,70,100
+",
+        )
+        .unwrap();
+
+        let map = isolate_data_from_csv(&csv_file).unwrap();
+        std::fs::remove_file(&csv_file).unwrap();
+
+        let residency = &map["Package-0"].residency;
+        let states: Vec<&str> = residency.keys().map(|v| v.as_str()).collect();
+        assert_eq!(states, vec!["core_c6", "pc2", "pc6"]);
+        let pc2 = &residency["pc2"];
+        assert_eq!((pc2.min, pc2.max, pc2.avg), (10.0, 30.0, 20.0));
+        // Samples without a reading are left out of the average.
+        assert_eq!(residency["pc6"].avg, 60.0);
+        assert_eq!(residency["core_c6"].total, 270.0);
+    }
+}
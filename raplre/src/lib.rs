@@ -0,0 +1,54 @@
+#[cfg(not(target_os = "linux"))]
+compile_error!("rust-rapl only support linux");
+
+#[macro_use]
+mod error;
+pub use error::*;
+
+pub mod arch;
+pub mod attribution;
+pub mod cap;
+mod common;
+mod cpuid;
+pub mod cstate;
+pub mod freq;
+mod isolate;
+pub mod limits;
+mod logger;
+pub mod models;
+pub mod placement;
+pub mod sampler;
+mod session;
+pub mod source;
+pub mod stats;
+pub mod thermal;
+pub mod ticker;
+
+pub use attribution::{
+    create_child_cgroup, remove_cgroup, resolve_cgroup, Attribution, CgroupAttribution,
+    ProcessAttribution, ProcessTarget,
+};
+pub use cap::{open_cap_control, AppliedCap, CapBackend, CapControl, CapGuard, PowerCap};
+pub use common::{
+    create_batch_summary, create_log_file_name, create_run_summary, create_summary_file_name,
+    detect_zones, parse_cpu_list, parse_duration, probe_zones, read_all_power_info,
+    read_isolated_data, read_last_measurement,
+};
+pub use cstate::{PackageCStates, ResidencySample};
+pub use freq::{FrequencySample, PackageFrequency};
+pub use isolate::isolate_data_from_csv;
+pub use limits::{
+    read_power_info, read_power_limits, DomainPowerLimits, PowerInfo, PowerLimit, RaplUnits,
+};
+pub use logger::{log_batch_summary, log_isolate_data, log_run_summary};
+pub use models::{
+    BatchSummary, ChildExit, IsolateData, IterationSummary, RAPLData, RAPLZone, RunStatus,
+    RunSummary, StatData, ThrottleCounter, ZoneStats, ZoneSummary,
+};
+pub use placement::{node_cpus, Placement};
+pub use sampler::{pin_current_thread, set_fifo_priority, thread_cpu_time, SamplerOverhead};
+pub use session::{Session, SessionConfig, SessionHandle, DEFAULT_INTERVAL};
+pub use source::{open_source, Backend, EnergySource, ZoneProbe, ZoneStatus};
+pub use stats::Stats;
+pub use thermal::{PackageThermal, ThermalSample};
+pub use ticker::{IntervalStats, Ticker};
@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+
+use crate::arch::{detect_vendor, intel_microarch, Vendor};
+use crate::common::get_core_of_package;
+use crate::error::*;
+
+pub(crate) const MSR_RAPL_POWER_UNIT: u32 = 0x606;
+pub(crate) const MSR_PKG_POWER_LIMIT: u32 = 0x610;
+pub(crate) const MSR_PKG_POWER_INFO: u32 = 0x614;
+pub(crate) const MSR_DRAM_POWER_LIMIT: u32 = 0x618;
+pub(crate) const MSR_DRAM_POWER_INFO: u32 = 0x61C;
+pub(crate) const MSR_PP0_POWER_LIMIT: u32 = 0x638;
+
+/// The units of `MSR_RAPL_POWER_UNIT`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RaplUnits {
+    /// Power Units (3:0), in Watts.
+    pub power_w: f64,
+    /// Energy Status Units (12:8), in Joules.
+    pub energy_j: f64,
+    /// Time Units (19:16), in seconds.
+    pub time_s: f64,
+}
+
+impl RaplUnits {
+    pub fn decode(raw: u64) -> Self {
+        let power = raw & 0xF;
+        let energy = (raw >> 8) & 0x1F;
+        let time = (raw >> 16) & 0xF;
+
+        Self {
+            power_w: 1.0 / (1u64 << power) as f64,
+            energy_j: 1.0 / (1u64 << energy) as f64,
+            time_s: 1.0 / (1u64 << time) as f64,
+        }
+    }
+}
+
+/// Read the RAPL units from the power unit register `which` on `core`.
+///
+/// AMD's `MSR_AMD_RAPL_POWER_UNIT` shares the layout of `MSR_RAPL_POWER_UNIT`.
+pub(crate) fn read_rapl_units(core: u32, which: u32) -> Result<RaplUnits, RError> {
+    Ok(RaplUnits::decode(crate::cpuid::read_msr(core, which)?))
+}
+
+/// Decode a time window field, Y in its bits 4:0 and Z in its bits 6:5, as
+/// `2^Y * (1.0 + Z/4.0) * time unit`.
+fn decode_time_window(field: u64, units: &RaplUnits) -> f64 {
+    let y = field & 0x1F;
+    let z = (field >> 5) & 0x3;
+
+    (1u64 << y) as f64 * (1.0 + z as f64 / 4.0) * units.time_s
+}
+
+/// A single power limit of a RAPL domain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerLimit {
+    /// Power limit, in Watts.
+    pub power_w: f64,
+    /// Time window the power is averaged over, in seconds.
+    pub time_window_s: f64,
+    pub enabled: bool,
+    /// Allow going below the OS requested P-states to honour the limit.
+    pub clamped: bool,
+}
+
+impl PowerLimit {
+    /// Decode the low 24 bits of `raw`.
+    ///
+    /// Power Limit (14:0), Enable (15), Clamping (16), Time Window Y (21:17)
+    /// and Z (23:22), where the time window is `2^Y * (1.0 + Z/4.0) * time unit`.
+    pub fn decode(raw: u64, units: &RaplUnits) -> Self {
+        Self {
+            power_w: (raw & 0x7FFF) as f64 * units.power_w,
+            time_window_s: decode_time_window(raw >> 17, units),
+            enabled: raw & (1 << 15) != 0,
+            clamped: raw & (1 << 16) != 0,
+        }
+    }
+}
+
+impl PowerLimit {
+    /// Encode the limit into the low 24 bits of a power limit register.
+    ///
+    /// The time window is rounded to the closest value that can be represented
+    /// as `2^Y * (1.0 + Z/4.0) * time unit`.
+    pub fn encode(&self, units: &RaplUnits) -> u64 {
+        let power = ((self.power_w / units.power_w).round() as u64).min(0x7FFF);
+
+        let mut window = (0u64, 0u64);
+        let mut best = f64::MAX;
+        for y in 0..32u64 {
+            for z in 0..4u64 {
+                let v = (1u64 << y) as f64 * (1.0 + z as f64 / 4.0) * units.time_s;
+                if (v - self.time_window_s).abs() < best {
+                    best = (v - self.time_window_s).abs();
+                    window = (y, z);
+                }
+            }
+        }
+
+        power
+            | (self.enabled as u64) << 15
+            | (self.clamped as u64) << 16
+            | window.0 << 17
+            | window.1 << 22
+    }
+
+    /// Replace the first limit (bits 23:0) of the power limit register value
+    /// `raw`, keeping the second limit and the lock bit as they are.
+    pub fn encode_into(&self, raw: u64, units: &RaplUnits) -> u64 {
+        (raw & !0xFF_FFFF) | self.encode(units)
+    }
+}
+
+/// The decoded power limit register of a RAPL domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainPowerLimits {
+    pub zone: String,
+    pub msr: u32,
+    pub raw: u64,
+    /// The register is locked until the next reset.
+    pub locked: bool,
+    /// PL1 and PL2 for the package, a single limit for the other domains.
+    pub limits: Vec<PowerLimit>,
+}
+
+impl DomainPowerLimits {
+    /// Decode a `MSR_PKG_POWER_LIMIT` value.
+    pub fn decode_package(pkg: u16, raw: u64, units: &RaplUnits) -> Self {
+        Self {
+            zone: format!("Package-{}", pkg),
+            msr: MSR_PKG_POWER_LIMIT,
+            raw,
+            locked: raw & (1 << 63) != 0,
+            limits: vec![
+                PowerLimit::decode(raw, units),
+                PowerLimit::decode(raw >> 32, units),
+            ],
+        }
+    }
+
+    /// Decode a `MSR_DRAM_POWER_LIMIT` value. DRAM has no clamping bit.
+    pub fn decode_dram(pkg: u16, raw: u64, units: &RaplUnits) -> Self {
+        let mut limit = PowerLimit::decode(raw, units);
+        limit.clamped = false;
+
+        Self {
+            zone: format!("Dram-{}", pkg),
+            msr: MSR_DRAM_POWER_LIMIT,
+            raw,
+            locked: raw & (1 << 31) != 0,
+            limits: vec![limit],
+        }
+    }
+
+    /// Decode a `MSR_PP0_POWER_LIMIT` value.
+    pub fn decode_pp0(pkg: u16, raw: u64, units: &RaplUnits) -> Self {
+        Self {
+            zone: format!("Pp0-{}", pkg),
+            msr: MSR_PP0_POWER_LIMIT,
+            raw,
+            locked: raw & (1 << 31) != 0,
+            limits: vec![PowerLimit::decode(raw, units)],
+        }
+    }
+}
+
+/// The decoded power info register of a RAPL domain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerInfo {
+    pub zone: String,
+    pub msr: u32,
+    pub raw: u64,
+    /// Thermal Spec Power (14:0), in Watts. This is the TDP of the package.
+    pub thermal_spec_w: f64,
+    /// Minimum Power (30:16), in Watts.
+    pub min_w: f64,
+    /// Maximum Power (46:32), in Watts.
+    pub max_w: f64,
+    /// Maximum Time Window (54:48), in seconds, encoded like the power limit windows.
+    pub max_time_window_s: f64,
+}
+
+impl PowerInfo {
+    /// Decode a `MSR_PKG_POWER_INFO` or `MSR_DRAM_POWER_INFO` value, which share their layout.
+    pub fn decode(zone: String, msr: u32, raw: u64, units: &RaplUnits) -> Self {
+        Self {
+            zone,
+            msr,
+            raw,
+            thermal_spec_w: (raw & 0x7FFF) as f64 * units.power_w,
+            min_w: ((raw >> 16) & 0x7FFF) as f64 * units.power_w,
+            max_w: ((raw >> 32) & 0x7FFF) as f64 * units.power_w,
+            max_time_window_s: decode_time_window(raw >> 48, units),
+        }
+    }
+}
+
+/// Read and decode the power info (TDP and power range) of package `pkg`.
+///
+/// Only Intel CPUs expose power info through MSRs.
+pub fn read_power_info(pkg: u16) -> Result<Vec<PowerInfo>, RError> {
+    if detect_vendor(pkg)? != Vendor::Intel {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "RAPL power info is only supported on Intel CPUs"
+        );
+    }
+
+    let core = match get_core_of_package(pkg) {
+        Some(core) => core,
+        None => {
+            crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+        }
+    };
+    let arch = intel_microarch(pkg)?;
+    let units = read_rapl_units(core, MSR_RAPL_POWER_UNIT)?;
+
+    let mut out = vec![PowerInfo::decode(
+        format!("Package-{}", pkg),
+        MSR_PKG_POWER_INFO,
+        crate::cpuid::read_msr(core, MSR_PKG_POWER_INFO)?,
+        &units,
+    )];
+
+    if arch.dram {
+        out.push(PowerInfo::decode(
+            format!("Dram-{}", pkg),
+            MSR_DRAM_POWER_INFO,
+            crate::cpuid::read_msr(core, MSR_DRAM_POWER_INFO)?,
+            &units,
+        ));
+    }
+
+    Ok(out)
+}
+
+/// Read and decode the RAPL power limits of package `pkg`.
+///
+/// Only Intel CPUs expose power limits through MSRs.
+pub fn read_power_limits(pkg: u16) -> Result<Vec<DomainPowerLimits>, RError> {
+    if detect_vendor(pkg)? != Vendor::Intel {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "RAPL power limits are only supported on Intel CPUs"
+        );
+    }
+
+    let core = match get_core_of_package(pkg) {
+        Some(core) => core,
+        None => {
+            crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+        }
+    };
+    let arch = intel_microarch(pkg)?;
+    let units = read_rapl_units(core, MSR_RAPL_POWER_UNIT)?;
+
+    let mut out = vec![DomainPowerLimits::decode_package(
+        pkg,
+        crate::cpuid::read_msr(core, MSR_PKG_POWER_LIMIT)?,
+        &units,
+    )];
+
+    if arch.dram {
+        let raw = crate::cpuid::read_msr(core, MSR_DRAM_POWER_LIMIT)?;
+        out.push(DomainPowerLimits::decode_dram(pkg, raw, &units));
+    }
+
+    if arch.pp0 {
+        let raw = crate::cpuid::read_msr(core, MSR_PP0_POWER_LIMIT)?;
+        out.push(DomainPowerLimits::decode_pp0(pkg, raw, &units));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Power in 1/8 W, energy in 2^-14 J, time in 1/1024 s.
+    const UNITS: u64 = 0xA0E03;
+
+    #[test]
+    fn power_info() {
+        let units = RaplUnits::decode(UNITS);
+        // 120 W TDP, 60 W min, 200 W max, max window Y=10 Z=2 (1.5 s).
+        let raw = 0x4A << 48 | 0x640 << 32 | 0x1E0 << 16 | 0x3C0;
+        let info = PowerInfo::decode("Package-0".to_string(), MSR_PKG_POWER_INFO, raw, &units);
+        assert_eq!(info.thermal_spec_w, 120.0);
+        assert_eq!(info.min_w, 60.0);
+        assert_eq!(info.max_w, 200.0);
+        assert_eq!(info.max_time_window_s, 1.5);
+    }
+
+    #[test]
+    fn package_power_limits() {
+        let units = RaplUnits::decode(UNITS);
+        // PL1: 125 W, enabled, clamped, Y=10 Z=0 (1 s).
+        let pl1 = 0x3E8 | 1 << 15 | 1 << 16 | 10 << 17;
+        // PL2: 200 W, enabled, not clamped, Y=1 Z=1 (2.5/1024 s).
+        let pl2 = 0x640 | 1 << 15 | 1 << 17 | 1 << 22;
+        let raw = 1 << 63 | pl2 << 32 | pl1;
+
+        let limits = DomainPowerLimits::decode_package(0, raw, &units);
+        assert!(limits.locked);
+        assert_eq!(
+            limits.limits,
+            vec![
+                PowerLimit {
+                    power_w: 125.0,
+                    time_window_s: 1.0,
+                    enabled: true,
+                    clamped: true,
+                },
+                PowerLimit {
+                    power_w: 200.0,
+                    time_window_s: 2.5 / 1024.0,
+                    enabled: true,
+                    clamped: false,
+                },
+            ]
+        );
+
+        let dram = DomainPowerLimits::decode_dram(0, 1 << 31 | pl1, &units);
+        assert!(dram.locked);
+        assert!(!dram.limits[0].clamped);
+        assert!(!DomainPowerLimits::decode_pp0(0, pl1, &units).locked);
+    }
+
+    #[test]
+    fn encode_power_limit() {
+        let units = RaplUnits::decode(UNITS);
+        for limit in [
+            PowerLimit {
+                power_w: 100.0,
+                time_window_s: 1.5,
+                enabled: true,
+                clamped: false,
+            },
+            PowerLimit {
+                power_w: 0.125,
+                time_window_s: 28.0,
+                enabled: false,
+                clamped: true,
+            },
+        ] {
+            assert_eq!(PowerLimit::decode(limit.encode(&units), &units), limit);
+        }
+
+        // Out of range values are rounded to the closest representable ones.
+        let rounded = PowerLimit {
+            power_w: 100.03,
+            time_window_s: 1.45,
+            enabled: true,
+            clamped: false,
+        };
+        let decoded = PowerLimit::decode(rounded.encode(&units), &units);
+        assert_eq!((decoded.power_w, decoded.time_window_s), (100.0, 1.5));
+    }
+
+    #[test]
+    fn encode_keeps_pl2_and_lock() {
+        let units = RaplUnits::decode(UNITS);
+        let pl2 = 0x640 | 1 << 15 | 1 << 17 | 1 << 22;
+        let raw = 1 << 63 | pl2 << 32 | 0x3E8 | 1 << 15 | 10 << 17;
+
+        let pl1 = PowerLimit {
+            power_w: 90.0,
+            time_window_s: 2.0,
+            enabled: true,
+            clamped: true,
+        };
+        let capped = pl1.encode_into(raw, &units);
+        assert_eq!(capped >> 24, raw >> 24);
+        let limits = DomainPowerLimits::decode_package(0, capped, &units);
+        assert!(limits.locked);
+        assert_eq!(limits.limits[0], pl1);
+        assert_eq!(limits.limits[1], PowerLimit::decode(pl2, &units));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::*;
 use crate::models;
 
 use csv;
@@ -39,7 +40,8 @@ pub(crate) fn log_poll_result(zone: &mut models::RAPLData, file_path: &PathBuf)
     }
 }
 
-pub(crate) fn log_isolate_data(file_name: PathBuf, map: HashMap<String, models::IsolateData>) {
+/// Write isolation data as pretty printed json.
+pub fn log_isolate_data(file_name: PathBuf, map: HashMap<String, models::IsolateData>) {
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
@@ -51,3 +53,43 @@ pub(crate) fn log_isolate_data(file_name: PathBuf, map: HashMap<String, models::
         .expect("Failed to write isolation data to file");
     drop(file);
 }
+
+/// Write the metadata of a run to `file_name` as json.
+pub fn log_run_summary(file_name: &PathBuf, summary: &models::RunSummary) -> Result<(), RError> {
+    let json = serde_json::to_string_pretty(summary).map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!("Failed to serialize run summary (error: {})", e),
+        )
+    })?;
+
+    write_json(file_name, json)
+}
+
+/// Write the summary of a batch of iterations to `file_name` as json.
+pub fn log_batch_summary(
+    file_name: &PathBuf,
+    summary: &models::BatchSummary,
+) -> Result<(), RError> {
+    let json = serde_json::to_string_pretty(summary).map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!("Failed to serialize batch summary (error: {})", e),
+        )
+    })?;
+
+    write_json(file_name, json)
+}
+
+fn write_json(file_name: &PathBuf, json: String) -> Result<(), RError> {
+    fs::write(file_name, json).map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!(
+                "Failed to write {} (error: {})",
+                file_name.to_str().unwrap(),
+                e
+            ),
+        )
+    })
+}
@@ -1,19 +1,10 @@
-#[cfg(not(target_os = "linux"))]
-compile_error!("rust-rapl only support linux");
-
 use clap::{Parser, Subcommand};
-use std::{os::unix::fs::PermissionsExt, path::PathBuf};
-
-#[macro_use]
-mod error;
-pub use error::*;
+use raplre::{Backend, CapBackend, Placement, PowerCap, SessionConfig};
+use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Duration};
 
 #[macro_use]
-mod common;
-mod cpuid;
-mod logger;
-mod models;
-mod task;
+mod display;
+mod signal;
 mod tool;
 
 #[derive(Debug, Parser)]
@@ -21,14 +12,14 @@ mod tool;
 #[command(name = "run_rapl")]
 #[command(about = "Power consumption measurement tool based on Intel RAPL")]
 struct Arg {
-    // #[arg(
-    //     short = 'd',
-    //     long = "delay",
-    //     default_value_t = 1000,
-    //     value_name = "MICROSECOND",
-    //     help = "Delay between polls (ms)"
-    // )]
-    // delay: u32,
+    #[arg(
+        long = "sample-interval",
+        default_value = "20ms",
+        value_name = "DURATION",
+        value_parser = parse_positive_duration,
+        help = "Interval between samples, e.g. 500us, 20ms or 1m (not --interval, which is the pause between benchmark runs)"
+    )]
+    sample_interval: Duration,
     #[arg(
         short = 't',
         long = "terminate-after",
@@ -54,10 +45,47 @@ using previously measurement idle data of the system consumption."
     isolate_file: Option<PathBuf>,
     #[arg(long = "dir", help = "Output directory")]
     output_dir: Option<PathBuf>,
+    #[arg(
+        short = 'b',
+        long = "backend",
+        default_value_t = Backend::Msr,
+        help = "Backend to read RAPL energy counters from"
+    )]
+    backend: Backend,
+    #[arg(
+        long = "core-temp",
+        help = "Also read the temperature of every core and report the hottest one of each package"
+    )]
+    core_temp: bool,
+    #[arg(
+        long = "freq",
+        help = "Sample APERF/MPERF on every core to report the effective frequency and busy percentage of each package"
+    )]
+    freq: bool,
+    #[arg(
+        long = "cstates",
+        help = "Sample the package C-state and core C6 residency of each package (always on for isolate)"
+    )]
+    cstates: bool,
+    #[arg(
+        long = "sampler-cpu",
+        value_name = "CPU",
+        help = "Pin the sampler to this cpu"
+    )]
+    sampler_cpu: Option<u32>,
+    #[arg(
+        long = "sampler-priority",
+        value_name = "PRIORITY",
+        value_parser = clap::value_parser!(i32).range(1..=99),
+        help = "Run the sampler with the SCHED_FIFO policy at this priority (1-99)"
+    )]
+    sampler_priority: Option<i32>,
     #[command(subcommand)]
     tool: Tool,
 }
 
+// Parsed once, the size of the benchmark options does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Tool {
     /// Live measurement
@@ -87,6 +115,12 @@ enum Tool {
             help = "Amount of times to run benchmark"
         )]
         count: u32,
+        #[arg(
+            long = "warmup",
+            default_value_t = 0,
+            help = "Amount of warm-up runs before the counted runs, left out of the batch statistics"
+        )]
+        warmup: u32,
         #[arg(
             long = "smooth",
             help = "Smoothing data based on EWMA",
@@ -100,9 +134,109 @@ enum Tool {
             default_value_t = 0
         )]
         interval: u32,
+        #[arg(
+            long = "cap",
+            value_name = "ZONE=W[,window=T]",
+            help = "Power cap applied while the benchmark runs, e.g. Package=120W,window=1s"
+        )]
+        caps: Vec<PowerCap>,
+        #[arg(
+            long = "cap-backend",
+            default_value_t = CapBackend::Msr,
+            help = "Backend to write power caps through (msr, powercap, simulated)"
+        )]
+        cap_backend: CapBackend,
+        #[arg(
+            long = "cap-dry-run",
+            help = "Print the power caps without writing them",
+            default_value_t = false
+        )]
+        cap_dry_run: bool,
+        #[arg(
+            long,
+            help = "Attribute the energy above idle power to the program by its share of busy CPU time per package (needs -i)",
+            default_value_t = false
+        )]
+        attribute: bool,
+        #[arg(
+            long,
+            value_name = "PARENT",
+            help = "Run the program in a fresh cgroup below the cgroup v2 PARENT, and attribute the energy above idle power to it (needs -i)"
+        )]
+        cgroup: Option<PathBuf>,
+        #[arg(
+            long = "child-output",
+            default_value_t = tool::ChildOutput::Null,
+            help = "Where the program's stdout and stderr go (null, inherit, or capture to files next to the .csv)"
+        )]
+        output: tool::ChildOutput,
+        #[arg(
+            long = "allow-failure",
+            help = "Keep going when the program exits with a non-zero status or is killed",
+            default_value_t = false
+        )]
+        allow_failure: bool,
+        #[arg(
+            long = "timeout",
+            value_name = "DURATION",
+            value_parser = parse_positive_duration,
+            help = "Stop an iteration after DURATION, e.g. 90s (defaults to -t)"
+        )]
+        timeout: Option<Duration>,
+        #[arg(
+            long = "kill-after",
+            value_name = "DURATION",
+            default_value = "5s",
+            value_parser = parse_positive_duration,
+            help = "Send SIGKILL when the program is still running DURATION after SIGTERM"
+        )]
+        kill_after: Duration,
+        #[arg(
+            long = "cpus",
+            value_name = "LIST",
+            help = "Bind the program to these cpus, e.g. 0-3,8"
+        )]
+        cpus: Option<String>,
+        #[arg(
+            long = "cpunodebind",
+            value_name = "NODES",
+            help = "Bind the program to the cpus of these NUMA nodes"
+        )]
+        cpunodebind: Option<String>,
+        #[arg(
+            long = "membind",
+            value_name = "NODES",
+            help = "Allocate the memory of the program from these NUMA nodes only"
+        )]
+        membind: Option<String>,
+        #[arg(
+            long = "sampler-off-cpus",
+            help = "Keep the sampler off the cpus of the program (pins it, see --sampler-cpu)",
+            default_value_t = false
+        )]
+        sampler_off_cpus: bool,
+    },
+    /// Measure the energy of a cgroup v2 group.
+    ///
+    /// Package, Pp0 and Dram energy above idle power is attributed to the cgroup by its CPU time
+    /// (usage_usec of cpu.stat) relative to the busy time of every package. Needs the idle data of
+    /// -i. Runs until the time limit (-t) or until interrupted.
+    Cgroup {
+        /// Path of the cgroup, absolute or relative to /sys/fs/cgroup
+        path: PathBuf,
     },
     /// List supported RAPL domain.
-    List,
+    ///
+    /// Every candidate domain is probed: its counter is read twice to check that it advances.
+    List {
+        #[arg(long = "json", help = "Print the probed domains as json")]
+        json: bool,
+    },
+    /// Print the RAPL power limits (PL1/PL2) of every package.
+    Limits {
+        #[arg(long = "json", help = "Print the power limits as json")]
+        json: bool,
+    },
     /// Pretty print last measurement of .csv file
     PrettyPrint {
         /// File to print from
@@ -144,8 +278,13 @@ enum Tool {
     },
 }
 
-// Disk Write: < 5 KB/s
-const POLL_DELAY: u32 = 20;
+fn parse_positive_duration(s: &str) -> Result<Duration, String> {
+    match raplre::parse_duration(s) {
+        Ok(duration) if duration.is_zero() => Err("the duration must be positive".to_string()),
+        Ok(duration) => Ok(duration),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
 fn main() {
     let arg = Arg::parse();
@@ -160,11 +299,22 @@ fn main() {
         }
     }
 
+    let session_config = SessionConfig {
+        backend: arg.backend,
+        interval: arg.sample_interval,
+        core_temp: arg.core_temp,
+        freq: arg.freq,
+        cstates: arg.cstates,
+        sampler_cpu: arg.sampler_cpu,
+        sampler_priority: arg.sampler_priority,
+        ..Default::default()
+    };
+
     if let Err(e) = match arg.tool {
         Tool::Live => {
-            crate::common::setup_ncurses();
+            display::setup_ncurses();
             crate::tool::live_measurement(
-                POLL_DELAY,
+                &session_config,
                 arg.run_time_limit,
                 arg.name.as_ref(),
                 arg.output_dir.as_ref(),
@@ -175,21 +325,68 @@ fn main() {
             program,
             args,
             count,
+            warmup,
             smooth,
             interval,
-        } => tool::do_benchmarks(
-            POLL_DELAY,
+            caps,
+            cap_backend,
+            cap_dry_run,
+            attribute,
+            cgroup,
+            output,
+            allow_failure,
+            timeout,
+            kill_after,
+            cpus,
+            cpunodebind,
+            membind,
+            sampler_off_cpus,
+        } => Placement::parse(
+            cpus.as_deref(),
+            cpunodebind.as_deref(),
+            membind.as_deref(),
+        )
+        .and_then(|placement| {
+            tool::do_benchmarks(
+                &session_config,
+                arg.name.as_ref(),
+                arg.output_dir.as_ref(),
+                arg.isolate_file.as_ref(),
+                &tool::BenchmarkConfig {
+                    runner,
+                    program,
+                    args,
+                    count,
+                    warmup,
+                    smooth,
+                    interval,
+                    caps,
+                    cap_backend,
+                    cap_dry_run,
+                    attribute,
+                    cgroup,
+                    output,
+                    allow_failure,
+                    timeout: timeout.or(arg
+                        .run_time_limit
+                        .filter(|limit| *limit > 0)
+                        .map(|limit| Duration::from_secs(limit as u64))),
+                    kill_after,
+                    placement,
+                    sampler_off_cpus,
+                },
+            )
+        }),
+        Tool::Cgroup { path } => tool::cgroup_measurement(
+            &session_config,
+            arg.run_time_limit,
             arg.name.as_ref(),
             arg.output_dir.as_ref(),
             arg.isolate_file.as_ref(),
-            runner,
-            program,
-            args,
-            count,
-            smooth,
-            interval,
+            &path,
         ),
-        Tool::List => tool::list(),
+        Tool::List { json } => tool::list(arg.backend, json),
+        Tool::Limits { json } => tool::limits(json),
         Tool::PrettyPrint { file } => tool::pretty_print(file),
         Tool::Isolate { measure, file } => {
             match file {
@@ -202,10 +399,10 @@ fn main() {
                     )
                 }
                 _ => {
-                    common::setup_ncurses();
+                    display::setup_ncurses();
                     // measure data basis
                     tool::measure_isolate_data(
-                        POLL_DELAY,
+                        &session_config,
                         arg.output_dir.as_ref(),
                         arg.name.as_ref().unwrap_or(&"default".to_string()),
                         measure,
@@ -1,40 +1,316 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+
 use serde;
 use serde::{Deserialize, Serialize};
 
+use crate::cap::AppliedCap;
+use crate::limits::PowerInfo;
+use crate::placement::Placement;
+use crate::sampler::SamplerOverhead;
+use crate::source::Backend;
+use crate::stats::Stats;
+use crate::ticker::IntervalStats;
+
+/// A RAPL domain and the register it is read from.
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub(crate) struct RAPLZone {
+pub struct RAPLZone {
     #[serde(skip_serializing, skip_deserializing)]
     pub core: u32,
     pub name: String,
     pub which: u32,
     pub unit: f64,
+    // largest raw counter value, the counter wraps around to zero after it
+    #[serde(default)]
+    pub range: u64,
+    // sysfs path describing the zone: the powercap zone directory or the perf event
+    #[serde(skip_serializing, skip_deserializing)]
+    pub path: Option<PathBuf>,
+    // perf status register counting the time the zone was throttled, if the zone has one
+    #[serde(skip_serializing, skip_deserializing)]
+    pub throttle: Option<ThrottleCounter>,
+}
+
+/// A perf status register, which accumulates the time a RAPL domain was
+/// throttled by its power limits.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ThrottleCounter {
+    pub which: u32,
+    /// Time of one counter increment, in seconds.
+    pub unit: f64,
+    /// Largest raw counter value, the counter wraps around to zero after it.
+    pub range: u64,
 }
 
+/// A sample of one RAPL domain, as written to the .csv output.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct RAPLData {
+pub struct RAPLData {
     #[serde(skip_serializing, skip_deserializing)]
     pub zone: RAPLZone,
     pub zone_name: String,
-    pub time_elapsed: u64,
+    // seconds from the start of the measurement to the read of the counter
+    pub time_elapsed: f64,
+    // wall-clock time of the read of the counter, in nanoseconds since the UNIX epoch
+    #[serde(default)]
+    pub timestamp_ns: u64,
+    // monotonic time of the last read of the counter, none before the first read
+    #[serde(skip_serializing, skip_deserializing)]
+    pub read_time: Option<Instant>,
+    // time the last read of the counter took
+    #[serde(skip_serializing, skip_deserializing)]
+    pub read_latency: Duration,
     pub prev_power_read: u64,
     pub total_power_j: f64, // total consumed energy in Joules from the begining of the measurement
     pub delta_power_j: f64,
     pub watt: f64,
     pub avg_watt: f64,
-    // pub temp: f64,
+    // raw counter increments from the begining of the measurement, extended to 64 bits across wraparounds
+    #[serde(default)]
+    pub total_raw: u64,
+    // the interval since the previous sample is long enough for the counter to wrap around more
+    // than once at the maximum plausible power of the zone, so delta_power_j may undercount
+    #[serde(default)]
+    pub gap: bool,
+    // number of samples flagged with gap from the begining of the measurement
+    #[serde(default)]
+    pub gaps: u64,
+    // temperature of the package of the zone in °C, empty if its thermal sensors cannot be read
+    #[serde(default)]
+    pub temp: Option<f64>,
+    // temperature of the hottest core of the package in °C, if per-core temperatures are read
+    #[serde(default)]
+    pub core_temp_max: Option<f64>,
+    // average effective frequency of the busy cores of the package in MHz, from APERF/MPERF
+    #[serde(default)]
+    pub freq_mhz: Option<f64>,
+    // share of the sample interval the cores of the package were busy, in percent
+    #[serde(default)]
+    pub busy_percent: Option<f64>,
+    // package C-state residency over the sample interval in percent, where the package reports it
+    #[serde(default)]
+    pub pc2_percent: Option<f64>,
+    #[serde(default)]
+    pub pc3_percent: Option<f64>,
+    #[serde(default)]
+    pub pc6_percent: Option<f64>,
+    #[serde(default)]
+    pub pc7_percent: Option<f64>,
+    #[serde(default)]
+    pub pc8_percent: Option<f64>,
+    #[serde(default)]
+    pub pc9_percent: Option<f64>,
+    #[serde(default)]
+    pub pc10_percent: Option<f64>,
+    // average core C6 residency of the package over the sample interval, in percent
+    #[serde(default)]
+    pub core_c6_percent: Option<f64>,
+    #[serde(default)]
+    pub prev_throttle_read: u64,
+    // total time the zone was throttled by its power limits from the begining of the measurement, in seconds
+    #[serde(default)]
+    pub throttled_s: f64,
+    #[serde(default)]
+    pub delta_throttled_s: f64,
+    // energy attributed to the measured workload from the begining of the measurement in Joules,
+    // empty unless the session has an attribution, see crate::attribution
+    #[serde(default)]
+    pub attributed_j: Option<f64>,
+    #[serde(default)]
+    pub delta_attributed_j: Option<f64>,
 }
 
+/// Idle consumption of one RAPL domain, used to isolate a measurement.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct IsolateData {
-    pub(crate) delta_power_j: StatData,
-    pub(crate) watt: StatData,
-    pub(crate) avg_watt: StatData,
+pub struct IsolateData {
+    pub delta_power_j: StatData,
+    pub watt: StatData,
+    pub avg_watt: StatData,
+    /// C-state residency of the package of the zone in percent, keyed by
+    /// state (`pc2`, ..., `pc10`, `core_c6`). A low deep C-state residency
+    /// indicates a noisy idle baseline.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub residency: BTreeMap<String, StatData>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct StatData {
+pub struct StatData {
     pub min: f64,
     pub max: f64,
     pub avg: f64,
     pub total: f64,
 }
+
+/// Energy consumed by one RAPL domain over a whole run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneSummary {
+    pub zone_name: String,
+    pub total_power_j: f64,
+    pub avg_watt: f64,
+    /// Number of samples taken after an interval in which the counter may have
+    /// wrapped around more than once, see [`RAPLData::gap`].
+    #[serde(default)]
+    pub gaps: u64,
+    /// Time the zone was throttled by its power limits, in seconds.
+    #[serde(default)]
+    pub throttled_s: f64,
+    /// Average power as a percentage of the TDP, for zones with power info.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_tdp_percent: Option<f64>,
+    /// Energy attributed to the measured workload, in Joules.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributed_j: Option<f64>,
+}
+
+impl From<&RAPLData> for ZoneSummary {
+    fn from(data: &RAPLData) -> Self {
+        Self {
+            zone_name: data.zone_name.clone(),
+            total_power_j: data.total_power_j,
+            avg_watt: data.avg_watt,
+            gaps: data.gaps,
+            throttled_s: data.throttled_s,
+            avg_tdp_percent: None,
+            attributed_j: data.attributed_j,
+        }
+    }
+}
+
+/// How a benchmarked program exited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChildExit {
+    /// Exit code, if the program exited on its own.
+    pub code: Option<i32>,
+    /// Signal that terminated the program.
+    pub signal: Option<i32>,
+}
+
+impl ChildExit {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl From<ExitStatus> for ChildExit {
+    fn from(status: ExitStatus) -> Self {
+        Self {
+            code: status.code(),
+            signal: status.signal(),
+        }
+    }
+}
+
+impl Display for ChildExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exit code {}", code),
+            (None, Some(signal)) => write!(f, "signal {}", signal),
+            (None, None) => write!(f, "unknown status"),
+        }
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunStatus {
+    #[default]
+    #[serde(rename = "completed")]
+    Completed,
+    /// The benchmarked program was stopped after its timeout.
+    #[serde(rename = "timed out")]
+    TimedOut,
+    /// The run was stopped by SIGINT or SIGTERM.
+    #[serde(rename = "interrupted")]
+    Interrupted,
+}
+
+/// Metadata of a run, written next to its .csv output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub name: String,
+    pub tool: String,
+    pub backend: Backend,
+    /// UNIX timestamp of the start of the run, in seconds.
+    pub start_time: u64,
+    pub duration_s: f64,
+    #[serde(default)]
+    pub status: RunStatus,
+    #[serde(default)]
+    pub caps: Vec<AppliedCap>,
+    /// Achieved sampling interval, for runs sampled on a schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<IntervalStats>,
+    /// Cost of the sampler itself, for runs that measured it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampler: Option<SamplerOverhead>,
+    /// How the attributed energy of the zones is estimated, for runs that attribute it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    /// How the benchmarked program exited, for benchmark runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit: Option<ChildExit>,
+    /// Cpus and NUMA nodes the benchmarked program was bound to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement: Option<Placement>,
+    /// TDP and power range of the packages, see [`crate::limits::read_power_info`].
+    #[serde(default)]
+    pub power_info: Vec<PowerInfo>,
+    pub zones: Vec<ZoneSummary>,
+}
+
+/// One iteration of a batch, see [`BatchSummary`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IterationSummary {
+    pub name: String,
+    /// Warm-up iterations are left out of the statistics.
+    #[serde(default)]
+    pub warmup: bool,
+    pub duration_s: f64,
+    #[serde(default)]
+    pub status: RunStatus,
+    pub zones: Vec<ZoneSummary>,
+}
+
+impl From<&RunSummary> for IterationSummary {
+    fn from(summary: &RunSummary) -> Self {
+        Self {
+            name: summary.name.clone(),
+            warmup: false,
+            duration_s: summary.duration_s,
+            status: summary.status,
+            zones: summary.zones.clone(),
+        }
+    }
+}
+
+/// Statistics of a zone over the measured iterations of a batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneStats {
+    pub zone_name: String,
+    /// Energy of an iteration, in Joules.
+    pub energy_j: Stats,
+    /// Average power of an iteration, in Watts.
+    pub avg_watt: Stats,
+    /// Attributed energy of an iteration, for batches that attribute it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributed_j: Option<Stats>,
+}
+
+/// Summary of a batch of iterations, written next to their .csv outputs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub name: String,
+    pub tool: String,
+    /// Number of warm-up iterations.
+    pub warmup: u32,
+    /// Every iteration, warm-up iterations included.
+    pub iterations: Vec<IterationSummary>,
+    /// Duration of an iteration, in seconds.
+    pub duration_s: Stats,
+    pub zones: Vec<ZoneStats>,
+}
@@ -0,0 +1,246 @@
+use std::{fs, os::unix::process::CommandExt, path::Path, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{online_cpus, parse_cpu_list};
+use crate::error::*;
+
+const NODE_ROOT: &str = "/sys/devices/system/node";
+// <linux/mempolicy.h>
+const MPOL_BIND: libc::c_int = 2;
+
+/// Where a benchmarked program runs and allocates memory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    /// CPUs the program is bound to, empty if it may run anywhere.
+    pub cpus: Vec<u32>,
+    /// NUMA nodes whose cpus the program is bound to.
+    #[serde(default)]
+    pub cpu_nodes: Vec<u32>,
+    /// NUMA nodes the program allocates memory from (`MPOL_BIND`), empty for the default policy.
+    #[serde(default)]
+    pub mem_nodes: Vec<u32>,
+}
+
+impl Placement {
+    /// Bind to `cpus` and to the cpus of `cpu_nodes`, like `numactl`: with both,
+    /// the program runs on the cpus that are in both sets.
+    pub fn new(
+        cpus: Option<Vec<u32>>,
+        cpu_nodes: Vec<u32>,
+        mem_nodes: Vec<u32>,
+    ) -> Result<Self, RError> {
+        let online = online_cpus();
+        let mut bound = match cpus {
+            Some(cpus) => {
+                if let Some(cpu) = cpus.iter().find(|cpu| !online.contains(cpu)) {
+                    crate::throw_rerr!(NOT_FOUND, "cpu {} is not online", cpu);
+                }
+                cpus
+            }
+            None => vec![],
+        };
+
+        if !cpu_nodes.is_empty() {
+            let mut node_cpus_all = vec![];
+            for node in cpu_nodes.iter() {
+                node_cpus_all.extend(node_cpus(*node)?);
+            }
+            bound = bind_cpus(bound, node_cpus_all);
+            if bound.is_empty() {
+                crate::throw_rerr!(
+                    INVALID_VALUE,
+                    "No cpu of the requested cpus in nodes {:?}",
+                    cpu_nodes
+                );
+            }
+        }
+        bound.sort_unstable();
+        bound.dedup();
+
+        for node in mem_nodes.iter() {
+            if !Path::new(NODE_ROOT).join(format!("node{}", node)).exists() {
+                crate::throw_rerr!(NOT_FOUND, "NUMA node {} does not exist", node);
+            }
+        }
+
+        Ok(Self {
+            cpus: bound,
+            cpu_nodes,
+            mem_nodes,
+        })
+    }
+
+    /// [`Placement::new`] from lists such as `0-3,8`.
+    pub fn parse(
+        cpus: Option<&str>,
+        cpu_nodes: Option<&str>,
+        mem_nodes: Option<&str>,
+    ) -> Result<Self, RError> {
+        Self::new(
+            cpus.map(parse_cpu_list).transpose()?,
+            cpu_nodes
+                .map(parse_cpu_list)
+                .transpose()?
+                .unwrap_or_default(),
+            mem_nodes
+                .map(parse_cpu_list)
+                .transpose()?
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty() && self.mem_nodes.is_empty()
+    }
+
+    /// Apply the placement to the process of `cmd` before it executes, with
+    /// `sched_setaffinity` and `set_mempolicy`.
+    pub fn apply_to(&self, cmd: &mut Command) {
+        if self.is_empty() {
+            return;
+        }
+
+        let cpu_set = if self.cpus.is_empty() {
+            None
+        } else {
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for cpu in self.cpus.iter() {
+                unsafe { libc::CPU_SET(*cpu as usize, &mut set) };
+            }
+            Some(set)
+        };
+
+        let bits = libc::c_ulong::BITS as usize;
+        let nodemask = nodemask(&self.mem_nodes);
+
+        // Only system calls, nothing is allocated between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(set) = cpu_set.as_ref() {
+                    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                // The kernel reads one bit less than maxnode.
+                if !nodemask.is_empty()
+                    && libc::syscall(
+                        libc::SYS_set_mempolicy,
+                        MPOL_BIND,
+                        nodemask.as_ptr(),
+                        nodemask.len() * bits + 1,
+                    ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// A cpu the program is not bound to, to run the sampler on: `preferred`
+    /// if it is one, otherwise the first such online cpu.
+    pub fn free_cpu(&self, preferred: Option<u32>) -> Result<u32, RError> {
+        if self.cpus.is_empty() {
+            crate::throw_rerr!(
+                INVALID_VALUE,
+                "The program is not bound to any cpu, there is no cpu to keep the sampler on"
+            );
+        }
+
+        match preferred {
+            Some(cpu) if self.cpus.contains(&cpu) => {
+                crate::throw_rerr!(
+                    INVALID_VALUE,
+                    "The sampler cpu {} is one of the cpus of the program",
+                    cpu
+                );
+            }
+            Some(cpu) => Ok(cpu),
+            None => match online_cpus()
+                .into_iter()
+                .find(|cpu| !self.cpus.contains(cpu))
+            {
+                Some(cpu) => Ok(cpu),
+                None => {
+                    crate::throw_rerr!(
+                        INVALID_VALUE,
+                        "The program is bound to every cpu, there is no cpu to keep the sampler on"
+                    );
+                }
+            },
+        }
+    }
+}
+
+/// The cpus of `cpus` that are in `node_cpus`, all of `node_cpus` if `cpus`
+/// is empty. Sorted and without duplicates.
+fn bind_cpus(cpus: Vec<u32>, node_cpus: Vec<u32>) -> Vec<u32> {
+    let mut bound = if cpus.is_empty() {
+        node_cpus
+    } else {
+        cpus.into_iter()
+            .filter(|cpu| node_cpus.contains(cpu))
+            .collect()
+    };
+    bound.sort_unstable();
+    bound.dedup();
+
+    bound
+}
+
+/// The `set_mempolicy` bitmask of `nodes`, one bit per node.
+fn nodemask(nodes: &[u32]) -> Vec<libc::c_ulong> {
+    let bits = libc::c_ulong::BITS as usize;
+    let mut mask: Vec<libc::c_ulong> = vec![];
+    for node in nodes.iter().map(|node| *node as usize) {
+        if mask.len() <= node / bits {
+            mask.resize(node / bits + 1, 0);
+        }
+        mask[node / bits] |= 1 << (node % bits);
+    }
+
+    mask
+}
+
+/// The cpus of NUMA node `node`.
+pub fn node_cpus(node: u32) -> Result<Vec<u32>, RError> {
+    let file = Path::new(NODE_ROOT)
+        .join(format!("node{}", node))
+        .join("cpulist");
+    let list = fs::read_to_string(&file).map_err(|e| {
+        new_custom_msg(
+            NOT_FOUND,
+            format!("NUMA node {} does not exist (error: {})", node, e),
+        )
+    })?;
+
+    parse_cpu_list(&list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodemask_words() {
+        assert!(nodemask(&[]).is_empty());
+        assert_eq!(nodemask(&[0, 3]), vec![0b1001]);
+
+        let bits = libc::c_ulong::BITS;
+        let mask = nodemask(&[1, bits - 1, bits, bits + 2]);
+        assert_eq!(mask.len(), 2);
+        assert_eq!(mask[0], (1 << (bits - 1)) | 0b10);
+        assert_eq!(mask[1], 0b101);
+    }
+
+    #[test]
+    fn cpus_and_nodes() {
+        // Only nodes: all their cpus.
+        assert_eq!(bind_cpus(vec![], vec![4, 5, 0, 1, 1]), vec![0, 1, 4, 5]);
+        // Both: the cpus in both sets.
+        assert_eq!(bind_cpus(vec![7, 1, 4, 1], vec![0, 1, 4, 5]), vec![1, 4]);
+        assert!(bind_cpus(vec![2, 3], vec![0, 1]).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::online_cpus;
+use crate::error::*;
+
+/// Pin the calling thread to `cpu`, which must be online.
+pub fn pin_current_thread(cpu: u32) -> Result<(), RError> {
+    if !online_cpus().contains(&cpu) {
+        crate::throw_rerr!(NOT_FOUND, "Sampler cpu {} is not online", cpu);
+    }
+
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu as usize, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "Failed to pin sampler to cpu {} (error: {})",
+                cpu,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the calling thread with the `SCHED_FIFO` real-time policy at `priority` (1-99).
+///
+/// This requires `CAP_SYS_NICE`, or an `RLIMIT_RTPRIO` of at least `priority`.
+pub fn set_fifo_priority(priority: i32) -> Result<(), RError> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } != 0 {
+        crate::throw_rerr!(
+            NOT_ALLOWED,
+            "Failed to set SCHED_FIFO priority {} (error: {})",
+            priority,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
+
+/// CPU time consumed by the calling thread so far.
+pub fn thread_cpu_time() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts);
+    }
+
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// The cost of sampling, as measured by the sampler itself.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplerOverhead {
+    /// The cpu the sampler was pinned to.
+    pub cpu: Option<u32>,
+    /// The `SCHED_FIFO` priority of the sampler.
+    pub priority: Option<i32>,
+    /// CPU time spent taking samples, in seconds.
+    pub cpu_time_s: f64,
+    /// CPU time spent taking samples, in percent of one cpu over the run.
+    pub cpu_percent: f64,
+    /// Number of energy counter reads, and their latency in seconds.
+    pub reads: u64,
+    pub read_latency_mean_s: f64,
+    pub read_latency_max_s: f64,
+    /// Estimated energy consumed by the sampler, in Joules.
+    pub energy_j: f64,
+    /// How `energy_j` is estimated.
+    pub model: String,
+}
+
+pub(crate) const OVERHEAD_MODEL: &str = "cpu_time_s * max over packages of (average Package power / number of cpus of the package)";
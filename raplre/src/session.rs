@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    attribution::{Attribution, ATTRIBUTED_DOMAINS},
+    common,
+    cstate::{open_cstates, PackageCStates},
+    error::*,
+    freq::{open_frequency, PackageFrequency},
+    models::{IsolateData, RAPLData},
+    sampler::{
+        pin_current_thread, set_fifo_priority, thread_cpu_time, SamplerOverhead, OVERHEAD_MODEL,
+    },
+    source::{open_source, Backend, EnergySource},
+    thermal::{open_thermal, PackageThermal},
+    ticker::{IntervalStats, Ticker},
+};
+
+/// Default interval between samples.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Configuration of a measurement session.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// The backend to read energy counters from.
+    pub backend: Backend,
+    /// Interval between samples, see [`Ticker`].
+    pub interval: Duration,
+    /// The .csv file every sample is appended to.
+    pub output_file: PathBuf,
+    /// Smoothing the instantaneous power based on EWMA.
+    pub smooth: bool,
+    /// Idle data to isolate the measurement from.
+    pub isolate_map: Option<HashMap<String, IsolateData>>,
+    /// Also read the temperature of every core, and report the hottest one per package.
+    pub core_temp: bool,
+    /// Sample APERF/MPERF on every core, and report the effective frequency and
+    /// busy percentage per package.
+    pub freq: bool,
+    /// Sample the package C-state and core C6 residency counters.
+    pub cstates: bool,
+    /// Pin the sampler to this cpu, see [`Session::setup_sampler_thread`].
+    pub sampler_cpu: Option<u32>,
+    /// Run the sampler with the `SCHED_FIFO` policy at this priority (1-99).
+    pub sampler_priority: Option<i32>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            interval: DEFAULT_INTERVAL,
+            output_file: PathBuf::default(),
+            smooth: false,
+            isolate_map: None,
+            core_temp: false,
+            freq: false,
+            cstates: false,
+            sampler_cpu: None,
+            sampler_priority: None,
+        }
+    }
+}
+
+/// A measurement session over all detected RAPL domains.
+///
+/// A session can either be polled by the caller with [`Session::sample`] and
+/// [`Session::wait`], or handed over to a measurement thread with [`Session::spawn`].
+/// Either way, samples are scheduled on the absolute deadlines of a [`Ticker`].
+pub struct Session {
+    config: SessionConfig,
+    source: Box<dyn EnergySource>,
+    zones: Vec<RAPLData>,
+    thermal: Vec<PackageThermal>,
+    frequency: Vec<PackageFrequency>,
+    cstates: Vec<PackageCStates>,
+    attribution: Option<Box<dyn Attribution>>,
+    start_time: Instant,
+    start_wall_time: SystemTime,
+    ticker: Ticker,
+    // cost of sampling: CPU time of the sampler and energy counter reads
+    cpu_time: Duration,
+    reads: u64,
+    read_latency_sum: Duration,
+    read_latency_max: Duration,
+}
+
+impl Session {
+    pub fn new(config: SessionConfig) -> Result<Self, RError> {
+        let source = open_source(config.backend)?;
+        let zones = common::setup_rapl_data(source.as_ref())?;
+        if zones.is_empty() {
+            crate::throw_rerr!(NOT_FOUND, "No supported RAPL domain found");
+        }
+        // Isolate data recorded with another backend or version may lack zones.
+        if let Some(map) = config.isolate_map.as_ref() {
+            if let Some(zone) = zones.iter().find(|z| !map.contains_key(&z.zone.name)) {
+                crate::throw_rerr!(
+                    NOT_FOUND,
+                    "No idle data for zone {} in the isolate file, record it again with `isolate`",
+                    zone.zone_name
+                );
+            }
+        }
+
+        let thermal = open_thermal(config.core_temp);
+        let frequency = if config.freq {
+            open_frequency()?
+        } else {
+            vec![]
+        };
+        let cstates = if config.cstates {
+            open_cstates()
+        } else {
+            vec![]
+        };
+
+        let ticker = Ticker::new(config.interval);
+        let start_time = Instant::now();
+        Ok(Self {
+            config,
+            source,
+            zones,
+            thermal,
+            frequency,
+            cstates,
+            attribution: None,
+            start_time,
+            start_wall_time: SystemTime::now(),
+            ticker,
+            cpu_time: Duration::ZERO,
+            reads: 0,
+            read_latency_sum: Duration::ZERO,
+            read_latency_max: Duration::ZERO,
+        })
+    }
+
+    pub fn start_time(&self) -> Instant {
+        self.start_time
+    }
+
+    pub fn output_file(&self) -> &PathBuf {
+        &self.config.output_file
+    }
+
+    /// The last sample of every zone.
+    pub fn zones(&self) -> &[RAPLData] {
+        &self.zones
+    }
+
+    /// Poll all zones once and append the result to the output file.
+    pub fn sample(&mut self) -> Result<&[RAPLData], RError> {
+        self.update(self.config.smooth)?;
+        Ok(&self.zones)
+    }
+
+    /// Sleep until the deadline of the next sample.
+    pub fn wait(&mut self) {
+        self.ticker.wait();
+    }
+
+    /// Attribute the dynamic energy of the packages to a workload with `attribution`.
+    ///
+    /// The share of the workload is sampled with the energy counters, and the
+    /// attributed energy of every interval is `share * Δenergy` of the Package,
+    /// Pp0 and Dram zones of the package, the idle power of
+    /// [`SessionConfig::isolate_map`] subtracted. Fails without idle data, as
+    /// the workload would be charged the idle power of the whole package.
+    pub fn set_attribution(&mut self, attribution: Box<dyn Attribution>) -> Result<(), RError> {
+        if self.config.isolate_map.is_none() {
+            crate::throw_rerr!(
+                INVALID_VALUE,
+                "Attributing energy needs idle data to subtract the idle power, record it with `isolate`"
+            );
+        }
+
+        for zone in self.zones.iter_mut().filter(|z| is_attributed(z)) {
+            zone.attributed_j = Some(0.0);
+            zone.delta_attributed_j = Some(0.0);
+        }
+        self.attribution = Some(attribution);
+
+        Ok(())
+    }
+
+    /// How the attributed energy is estimated, if the session attributes it.
+    pub fn attribution_model(&self) -> Option<String> {
+        let attribution = self.attribution.as_ref()?;
+
+        Some(format!(
+            "energy above the idle power of the isolate data of every sample interval: {}",
+            attribution.model()
+        ))
+    }
+
+    /// Pin the calling thread and set its scheduling policy, as configured by
+    /// [`SessionConfig::sampler_cpu`] and [`SessionConfig::sampler_priority`].
+    ///
+    /// The measurement thread of [`Session::spawn`] does this by itself. When
+    /// polling with [`Session::sample`], call it from the polling thread.
+    pub fn setup_sampler_thread(&self) -> Result<(), RError> {
+        if let Some(cpu) = self.config.sampler_cpu {
+            pin_current_thread(cpu)?;
+        }
+        if let Some(priority) = self.config.sampler_priority {
+            set_fifo_priority(priority)?;
+        }
+
+        Ok(())
+    }
+
+    /// The cost of sampling so far.
+    ///
+    /// The energy used by the sampler is estimated from its CPU time, at the
+    /// average power per cpu of the busiest package.
+    pub fn overhead(&self) -> SamplerOverhead {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let cpu_time_s = self.cpu_time.as_secs_f64();
+
+        let mut watt_per_cpu: f64 = 0.0;
+        for zone in self
+            .zones
+            .iter()
+            .filter(|z| z.zone_name.starts_with("Package-"))
+        {
+            let cpus = common::package_of_core(zone.zone.core)
+                .map(|pkg| common::cores_of_package(pkg).len())
+                .unwrap_or(0);
+            if cpus > 0 {
+                watt_per_cpu = watt_per_cpu.max(zone.avg_watt / cpus as f64);
+            }
+        }
+
+        SamplerOverhead {
+            cpu: self.config.sampler_cpu,
+            priority: self.config.sampler_priority,
+            cpu_time_s,
+            cpu_percent: if elapsed > 0.0 {
+                cpu_time_s * 100.0 / elapsed
+            } else {
+                0.0
+            },
+            reads: self.reads,
+            read_latency_mean_s: match self.reads {
+                0 => 0.0,
+                reads => self.read_latency_sum.as_secs_f64() / reads as f64,
+            },
+            read_latency_max_s: self.read_latency_max.as_secs_f64(),
+            energy_j: cpu_time_s * watt_per_cpu,
+            model: OVERHEAD_MODEL.to_string(),
+        }
+    }
+
+    /// The achieved sampling interval so far.
+    pub fn interval_stats(&self) -> IntervalStats {
+        self.ticker.stats()
+    }
+
+    fn update(&mut self, smooth: bool) -> Result<(), RError> {
+        let cpu_time = thread_cpu_time();
+        for thermal in self.thermal.iter() {
+            let sample = thermal.read()?;
+            for zone in zones_of_package(&mut self.zones, thermal.pkg) {
+                zone.temp = sample.package;
+                zone.core_temp_max = sample.core_max;
+            }
+        }
+        for frequency in self.frequency.iter_mut() {
+            let sample = frequency.sample()?;
+            for zone in zones_of_package(&mut self.zones, frequency.pkg) {
+                zone.freq_mhz = Some(sample.freq_mhz);
+                zone.busy_percent = Some(sample.busy_percent);
+            }
+        }
+        for cstates in self.cstates.iter_mut() {
+            let sample = cstates.sample()?;
+            for zone in zones_of_package(&mut self.zones, cstates.pkg) {
+                zone.pc2_percent = sample.package[0];
+                zone.pc3_percent = sample.package[1];
+                zone.pc6_percent = sample.package[2];
+                zone.pc7_percent = sample.package[3];
+                zone.pc8_percent = sample.package[4];
+                zone.pc9_percent = sample.package[5];
+                zone.pc10_percent = sample.package[6];
+                zone.core_c6_percent = sample.core_c6;
+            }
+        }
+
+        let shares = match self.attribution.as_mut() {
+            Some(attribution) => Some(attribution.sample()?),
+            None => None,
+        };
+
+        common::update_measurements(
+            self.source.as_ref(),
+            &mut self.zones,
+            self.start_time,
+            self.start_wall_time,
+            self.config.isolate_map.as_ref(),
+            smooth,
+        )?;
+
+        if let Some(shares) = shares {
+            for zone in self.zones.iter_mut().filter(|z| is_attributed(z)) {
+                let share = common::package_of_core(zone.zone.core)
+                    .and_then(|pkg| shares.get(pkg as usize).cloned())
+                    .unwrap_or(0.0);
+                let delta = zone.delta_power_j.max(0.0) * share;
+                zone.delta_attributed_j = Some(delta);
+                zone.attributed_j = Some(zone.attributed_j.unwrap_or(0.0) + delta);
+            }
+        }
+
+        for zone in self.zones.iter_mut() {
+            crate::logger::log_poll_result(zone, &self.config.output_file);
+        }
+
+        for zone in self.zones.iter() {
+            self.reads += 1;
+            self.read_latency_sum += zone.read_latency;
+            self.read_latency_max = self.read_latency_max.max(zone.read_latency);
+        }
+        self.cpu_time += thread_cpu_time().saturating_sub(cpu_time);
+
+        Ok(())
+    }
+
+    /// Move the session into a measurement thread sampling every `interval`.
+    pub fn spawn(self) -> Result<SessionHandle, RError> {
+        let (send, recv) = mpsc::channel();
+        let (ready_send, ready_recv) = mpsc::channel();
+        let handle = SessionHandle {
+            send,
+            thr: spawn_measurement_thread(self, recv, ready_send),
+        };
+
+        // Wait for the sampler to be set up, so that a failure is reported right away.
+        match ready_recv.recv() {
+            Ok(()) => Ok(handle),
+            Err(_) => {
+                handle.join()?;
+                crate::throw_rerr!(INTERNAL_ERR, "Measurement thread stopped before sampling");
+            }
+        }
+    }
+}
+
+/// The zones of package `pkg`.
+fn zones_of_package(zones: &mut [RAPLData], pkg: u16) -> impl Iterator<Item = &mut RAPLData> {
+    zones
+        .iter_mut()
+        .filter(move |z| common::package_of_core(z.zone.core) == Some(pkg))
+}
+
+/// Zones whose energy is attributed, see [`Session::set_attribution`].
+fn is_attributed(zone: &RAPLData) -> bool {
+    let domain = zone.zone_name.split('-').next().unwrap_or_default();
+    ATTRIBUTED_DOMAINS.contains(&domain)
+}
+
+/// Handle of a session running in a measurement thread.
+pub struct SessionHandle {
+    send: Sender<i8>,
+    thr: JoinHandle<Result<Session, RError>>,
+}
+
+impl SessionHandle {
+    /// Stop the measurement thread and return the last sample of every zone.
+    pub fn stop(self) -> Result<Vec<RAPLData>, RError> {
+        Ok(self.join()?.zones)
+    }
+
+    /// Stop the measurement thread and return the session, e.g. to read its
+    /// [`Session::interval_stats`].
+    pub fn join(self) -> Result<Session, RError> {
+        // The thread has already stopped if it failed to read a counter.
+        let _ = self.send.send(common::THREAD_KILL);
+        self.thr.join().map_err(|_| {
+            new_simple_msg(
+                INTERNAL_ERR,
+                "Failed to wait for measurement thread to finish",
+            )
+        })?
+    }
+}
+
+fn spawn_measurement_thread(
+    mut session: Session,
+    recv: Receiver<i8>,
+    ready: Sender<()>,
+) -> JoinHandle<Result<Session, RError>> {
+    thread::spawn(move || {
+        let smooth = session.config.smooth;
+        session.setup_sampler_thread()?;
+        let _ = ready.send(());
+
+        loop {
+            session.update(smooth)?;
+
+            match recv.recv_timeout(session.ticker.remaining()) {
+                Ok(msg) => {
+                    if msg == common::THREAD_KILL {
+                        session.update(false)?;
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => session.ticker.tick(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        Ok(session)
+    })
+}
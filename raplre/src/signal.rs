@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// Last SIGINT/SIGTERM received, 0 if none
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    RECEIVED.store(sig, Ordering::SeqCst);
+}
+
+/// Catch SIGINT and SIGTERM instead of terminating, so that the benchmark
+/// can forward them to its child, write the final sample and summary, and
+/// restore the power limits before exiting.
+pub(crate) fn catch_termination() {
+    for sig in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            libc::signal(sig, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+}
+
+/// The termination signal received since [`catch_termination`], if any.
+pub(crate) fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
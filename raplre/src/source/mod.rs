@@ -0,0 +1,138 @@
+use std::{fmt::Display, fs, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::*;
+use crate::models::RAPLZone;
+
+mod msr;
+mod perf;
+mod powercap;
+mod probe;
+#[cfg(test)]
+pub(crate) mod testing;
+pub use msr::*;
+pub use perf::*;
+pub use powercap::*;
+pub use probe::*;
+
+/// Backends that raplre can read RAPL energy counters from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Model specific registers, read through libcpuid.
+    #[default]
+    Msr,
+    /// The Linux powercap framework, `/sys/class/powercap/intel-rapl*`.
+    Powercap,
+    /// The `power` perf PMU, `/sys/bus/event_source/devices/power`.
+    Perf,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Msr => write!(f, "msr"),
+            Backend::Powercap => write!(f, "powercap"),
+            Backend::Perf => write!(f, "perf"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "msr" => Ok(Backend::Msr),
+            "powercap" => Ok(Backend::Powercap),
+            "perf" => Ok(Backend::Perf),
+            _ => Err(format!(
+                "unknown backend `{}` (expected one of: msr, powercap, perf)",
+                s
+            )),
+        }
+    }
+}
+
+/// A source of RAPL energy counters.
+///
+/// A source enumerates the domains it can read as [`RAPLZone`]s. The zones are
+/// then passed back to the source to read their raw energy counter.
+pub trait EnergySource: Send + Sync {
+    /// The backend implemented by this source.
+    fn backend(&self) -> Backend;
+
+    /// Enumerate the candidate RAPL domains of this source, see [`probe`] to
+    /// check which of them can actually be read.
+    fn domains(&self) -> Result<Vec<RAPLZone>, RError>;
+
+    /// Read the raw energy counter of `zone`.
+    fn read(&self, zone: &RAPLZone) -> Result<u64, RError>;
+
+    /// Energy of one counter increment of `zone`, in Joules.
+    fn unit(&self, zone: &RAPLZone) -> f64 {
+        zone.unit
+    }
+
+    /// The raw counter value at which the counter of `zone` wraps around.
+    fn wrap_range(&self, zone: &RAPLZone) -> u64 {
+        zone.range
+    }
+
+    /// The maximum plausible power of `zone`, in Watts.
+    ///
+    /// Used to detect sampling intervals long enough for the counter to wrap
+    /// around more than once. The defaults are generous upper bounds per domain.
+    fn max_power(&self, zone: &RAPLZone) -> f64 {
+        let domain = zone.name.split('-').next().unwrap_or_default();
+        match domain {
+            "Package" | "Pp0" => 1000.0,
+            "Dram" => 500.0,
+            "Pp1" => 250.0,
+            "Psys" => 2000.0,
+            // AMD per-core zones
+            "Core" => 100.0,
+            _ => 1000.0,
+        }
+    }
+
+    /// Read the raw throttled time counter of `zone`, see [`RAPLZone::throttle`].
+    ///
+    /// Sources that cannot read it return `None`.
+    fn read_throttle(&self, _zone: &RAPLZone) -> Result<Option<u64>, RError> {
+        Ok(None)
+    }
+}
+
+/// Open the energy source of `backend`.
+pub fn open_source(backend: Backend) -> Result<Box<dyn EnergySource>, RError> {
+    match backend {
+        Backend::Msr => Ok(Box::new(MsrSource::new())),
+        Backend::Powercap => Ok(Box::new(PowercapSource::new())),
+        Backend::Perf => Ok(Box::new(PerfSource::new())),
+    }
+}
+
+fn read_attr(path: &PathBuf) -> Result<String, RError> {
+    fs::read_to_string(path)
+        .map(|v| v.trim().to_string())
+        .map_err(|e| {
+            new_custom_msg(
+                IOERR,
+                format!("Failed to read {} (error: {})", path.display(), e),
+            )
+        })
+}
+
+fn parse_attr<T: FromStr>(path: &PathBuf) -> Result<T, RError>
+where
+    T::Err: Display,
+{
+    read_attr(path)?.parse().map_err(|e| {
+        new_custom_msg(
+            INVALID_VALUE,
+            format!("Failed to parse {} (error: {})", path.display(), e),
+        )
+    })
+}
@@ -0,0 +1,242 @@
+use crate::arch::{detect_vendor, intel_microarch, EnergyUnit, Vendor};
+use crate::common::{get_core_of_package, physical_cores_of_package};
+use crate::error::*;
+use crate::limits::{read_rapl_units, MSR_RAPL_POWER_UNIT};
+use crate::models::{RAPLZone, ThrottleCounter};
+
+use super::{Backend, EnergySource};
+
+/// RAPL energy counters read from model specific registers through libcpuid.
+///
+/// The MSR energy status registers are 32-bit wide. Intel CPUs and AMD Zen
+/// CPUs are supported, the latter with a zone per physical core.
+#[derive(Debug, Default)]
+pub struct MsrSource {}
+
+impl MsrSource {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl EnergySource for MsrSource {
+    fn backend(&self) -> Backend {
+        Backend::Msr
+    }
+
+    fn domains(&self) -> Result<Vec<RAPLZone>, RError> {
+        let mut zones = vec![];
+        for pkg in 0..cpu::topology().max_num_packages() {
+            let core = match get_core_of_package(pkg) {
+                Some(core) => core,
+                None => {
+                    crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+                }
+            };
+
+            match detect_vendor(pkg)? {
+                Vendor::Intel => intel_domains(pkg, core, &mut zones)?,
+                Vendor::Amd => amd_domains(pkg, core, &mut zones)?,
+            }
+        }
+
+        Ok(zones)
+    }
+
+    fn read(&self, zone: &RAPLZone) -> Result<u64, RError> {
+        // The upper 32 bits of the energy status registers are reserved.
+        Ok(crate::cpuid::read_msr(zone.core, zone.which)? & MSR_COUNTER_RANGE)
+    }
+
+    fn read_throttle(&self, zone: &RAPLZone) -> Result<Option<u64>, RError> {
+        match zone.throttle {
+            Some(throttle) => Ok(Some(
+                crate::cpuid::read_msr(zone.core, throttle.which)? & MSR_COUNTER_RANGE,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+// The energy and perf status registers are 32-bit counters.
+const MSR_COUNTER_RANGE: u64 = u32::MAX as u64;
+const MSR_AMD_RAPL_POWER_UNIT: u32 = 0xC0010299;
+const MSR_PKG_PERF_STATUS: u32 = 0x613;
+const MSR_DRAM_PERF_STATUS: u32 = 0x61B;
+
+/// The perf status register `which`, if it can be read on `core`.
+///
+/// Not every model implements the perf status registers, reading them fails then.
+fn throttle_counter(core: u32, which: u32, unit: f64) -> Option<ThrottleCounter> {
+    match crate::cpuid::read_msr(core, which) {
+        Ok(_) => Some(ThrottleCounter {
+            which,
+            unit,
+            range: MSR_COUNTER_RANGE,
+        }),
+        Err(e) => {
+            crate::ignore_rerr!(e);
+            None
+        }
+    }
+}
+
+fn intel_domains(pkg: u16, core: u32, zones: &mut Vec<RAPLZone>) -> Result<(), RError> {
+    let arch = intel_microarch(pkg)?;
+    let units = read_rapl_units(core, MSR_RAPL_POWER_UNIT)?;
+    let unit = units.energy_j;
+    let time_unit = units.time_s;
+
+    // MSR_PP0_ENERGY_STATUS
+    // MSR_PP0_ENERGY_STATUS/MSR_PP1_ENERGY_STATUS are read-only MSRs. They report the actual energy use
+    // for the respective power plane domains. These MSRs are updated every ~1msec.
+    //
+    // Total Energy Consumed (31:0): The unsigned integer value represents the total amount of energy consumed since the last time this register was cleared.
+    // The unit of this field is specified by the “Energy Status Units” field of MSR_RAPL_POWER_UNIT.
+    if arch.pp0 {
+        zones.push(RAPLZone {
+            core,
+            name: format!("Pp0-{}", pkg),
+            which: 1593,
+            unit,
+            range: MSR_COUNTER_RANGE,
+            path: None,
+            throttle: None,
+        });
+    }
+
+    // MSR_PP1_ENERGY_STATUS
+    //
+    // Energy consumed by the graphics engine of client parts.
+    if arch.pp1 {
+        zones.push(RAPLZone {
+            core,
+            name: format!("Pp1-{}", pkg),
+            which: 1601,
+            unit,
+            range: MSR_COUNTER_RANGE,
+            path: None,
+            throttle: None,
+        });
+    }
+
+    // MSR_DRAM_ENERGY_STATUS
+    if arch.dram {
+        zones.push(RAPLZone {
+            core,
+            name: format!("Dram-{}", pkg),
+            which: 1561,
+            unit: match arch.dram_unit {
+                EnergyUnit::Package => unit,
+                EnergyUnit::Fixed(unit) => unit,
+            },
+            range: MSR_COUNTER_RANGE,
+            path: None,
+            throttle: throttle_counter(core, MSR_DRAM_PERF_STATUS, time_unit),
+        });
+    }
+
+    // MSR_PKG_ENERGY_STATUS
+    //
+    // MSR_PKG_PERF_STATUS (and MSR_DRAM_PERF_STATUS for the DRAM zone) accumulates
+    // the time the package was throttled by its power limits, in time units.
+    //
+    // Total energy consumption in J (32.0), in 10nsec units.
+    zones.push(RAPLZone {
+        core,
+        name: format!("Package-{}", pkg),
+        which: 1553,
+        unit,
+        range: MSR_COUNTER_RANGE,
+        path: None,
+        throttle: throttle_counter(core, MSR_PKG_PERF_STATUS, time_unit),
+    });
+
+    // MSR_PLATFORM_ENERGY_STATUS
+    //
+    // Energy consumed by the whole platform (SoC, memory, and the components
+    // powered by the same supply), when the platform reports it. The register
+    // is platform wide, so it is only listed once, read on package 0.
+    if arch.psys && pkg == 0 {
+        zones.push(RAPLZone {
+            core,
+            name: format!("Psys-{}", pkg),
+            which: 1613,
+            unit: match arch.psys_unit {
+                EnergyUnit::Package => unit,
+                EnergyUnit::Fixed(unit) => unit,
+            },
+            range: MSR_COUNTER_RANGE,
+            path: None,
+            throttle: None,
+        });
+    }
+
+    Ok(())
+}
+
+fn amd_domains(pkg: u16, core: u32, zones: &mut Vec<RAPLZone>) -> Result<(), RError> {
+    // MSR_AMD_RAPL_POWER_UNIT shares its layout with the Intel MSR_RAPL_POWER_UNIT.
+    let unit = read_energy_unit(core, MSR_AMD_RAPL_POWER_UNIT)?;
+    amd_zones(pkg, core, &physical_cores_of_package(pkg), unit, zones);
+
+    Ok(())
+}
+
+/// The zones of AMD package `pkg`, read on `core`, with a core zone for every
+/// physical core of `cores`.
+fn amd_zones(pkg: u16, core: u32, cores: &[u32], unit: f64, zones: &mut Vec<RAPLZone>) {
+    const MSR_AMD_CORE_ENERGY_STATUS: u32 = 0xC001029A;
+    const MSR_AMD_PKG_ENERGY_STATUS: u32 = 0xC001029B;
+
+    // MSR_AMD_CORE_ENERGY_STATUS
+    //
+    // Core energy is accounted per physical core, SMT siblings read the same counter.
+    for lcore in cores.iter().cloned() {
+        zones.push(RAPLZone {
+            core: lcore,
+            name: format!("Core-{}", lcore),
+            which: MSR_AMD_CORE_ENERGY_STATUS,
+            unit,
+            range: MSR_COUNTER_RANGE,
+            path: None,
+            throttle: None,
+        });
+    }
+
+    // MSR_AMD_PKG_ENERGY_STATUS
+    zones.push(RAPLZone {
+        core,
+        name: format!("Package-{}", pkg),
+        which: MSR_AMD_PKG_ENERGY_STATUS,
+        unit,
+        range: MSR_COUNTER_RANGE,
+        path: None,
+        throttle: None,
+    });
+}
+
+fn read_energy_unit(core: u32, which: u32) -> Result<f64, RError> {
+    Ok(read_rapl_units(core, which)?.energy_j)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amd_zone_names() {
+        let mut zones = vec![];
+        amd_zones(1, 32, &[32, 34, 36], 1.0 / 65536.0, &mut zones);
+
+        let names: Vec<&str> = zones.iter().map(|z| z.name.as_str()).collect();
+        assert_eq!(names, vec!["Core-32", "Core-34", "Core-36", "Package-1"]);
+        let cores: Vec<u32> = zones.iter().map(|z| z.core).collect();
+        assert_eq!(cores, vec![32, 34, 36, 32]);
+        assert_eq!(zones[1].which, 0xC001029A);
+        assert_eq!(zones[3].which, 0xC001029B);
+        assert!(zones
+            .iter()
+            .all(|z| z.unit == 1.0 / 65536.0 && z.range == MSR_COUNTER_RANGE));
+    }
+}
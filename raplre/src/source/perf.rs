@@ -0,0 +1,227 @@
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::common::{package_of_core, parse_cpu_list};
+use crate::error::*;
+use crate::models::RAPLZone;
+
+use super::{parse_attr, read_attr, Backend, EnergySource};
+
+const POWER_PMU_ROOT: &str = "/sys/bus/event_source/devices/power";
+
+/// The leading fields of `struct perf_event_attr` (PERF_ATTR_SIZE_VER0).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct perf_event_attr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+const PERF_ATTR_SIZE_VER0: u32 = 64;
+
+/// RAPL energy counters read from the `power` perf PMU.
+///
+/// Every `energy-*` event of the PMU is opened once per package, on the cpu
+/// listed for the package in the PMU `cpumask`. The kernel extends the
+/// counters to 64 bits, the energy of one increment is given by the
+/// `<event>.scale` file.
+#[derive(Debug)]
+pub struct PerfSource {
+    root: PathBuf,
+    events: Mutex<HashMap<(u32, u32), File>>,
+}
+
+impl PerfSource {
+    pub fn new() -> Self {
+        Self::with_root(POWER_PMU_ROOT)
+    }
+
+    /// Create a source reading the PMU description from `root` instead of
+    /// `/sys/bus/event_source/devices/power`.
+    pub fn with_root<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn open_event(&self, cpu: u32, config: u32) -> Result<File, RError> {
+        let pmu_type: u32 = parse_attr(&self.root.join("type"))?;
+        let mut attr = perf_event_attr {
+            type_: pmu_type,
+            size: PERF_ATTR_SIZE_VER0,
+            config: config as u64,
+            ..Default::default()
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &mut attr as *mut perf_event_attr,
+                -1 as libc::pid_t,
+                cpu as libc::c_int,
+                -1 as libc::c_int,
+                0 as libc::c_ulong,
+            )
+        };
+        if fd < 0 {
+            crate::throw_rerr!(
+                IOERR,
+                "Failed to open perf event {:#x} at cpu {}. (error: {})",
+                config,
+                cpu,
+                std::io::Error::last_os_error()
+            );
+        }
+
+        Ok(unsafe { File::from_raw_fd(fd as i32) })
+    }
+}
+
+impl Default for PerfSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnergySource for PerfSource {
+    fn backend(&self) -> Backend {
+        Backend::Perf
+    }
+
+    fn domains(&self) -> Result<Vec<RAPLZone>, RError> {
+        let cpus = parse_cpu_list(&read_attr(&self.root.join("cpumask"))?)?;
+        let events_dir = self.root.join("events");
+        let entries = fs::read_dir(&events_dir).map_err(|e| {
+            new_custom_msg(
+                NOT_FOUND,
+                format!("Failed to open {} (error: {})", events_dir.display(), e),
+            )
+        })?;
+
+        let mut events = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|e| {
+                new_custom_msg(
+                    IOERR,
+                    format!("Failed to read {} (error: {})", events_dir.display(), e),
+                )
+            })?;
+            let event = entry.file_name().to_string_lossy().to_string();
+            let domain = match event.as_str() {
+                "energy-pkg" => "Package",
+                "energy-cores" => "Pp0",
+                "energy-gpu" => "Pp1",
+                "energy-ram" => "Dram",
+                "energy-psys" => "Psys",
+                _ => continue,
+            };
+
+            if read_attr(&events_dir.join(format!("{}.unit", event)))? != "Joules" {
+                continue;
+            }
+
+            // event=0x02
+            let desc = read_attr(&entry.path())?;
+            let config = desc
+                .split(',')
+                .find_map(|v| v.strip_prefix("event="))
+                .and_then(|v| u32::from_str_radix(v.trim_start_matches("0x"), 16).ok());
+            let config = match config {
+                Some(config) => config,
+                None => {
+                    crate::throw_rerr!(
+                        INVALID_VALUE,
+                        "Failed to parse perf event `{}` ({})",
+                        event,
+                        desc
+                    );
+                }
+            };
+            let scale: f64 = parse_attr(&events_dir.join(format!("{}.scale", event)))?;
+
+            events.push((domain, config, scale, entry.path()));
+        }
+        events.sort_by_key(|(_, config, _, _)| *config);
+
+        let mut zones = vec![];
+        for (idx, cpu) in cpus.into_iter().enumerate() {
+            let pkg = package_of_core(cpu).map(|v| v as usize).unwrap_or(idx);
+            for (domain, config, scale, path) in events.iter() {
+                // The platform counter is not per package, list it once.
+                if *domain == "Psys" && idx > 0 {
+                    continue;
+                }
+                zones.push(RAPLZone {
+                    core: cpu,
+                    name: format!("{}-{}", domain, pkg),
+                    which: *config,
+                    unit: *scale,
+                    range: u64::MAX,
+                    path: Some(path.clone()),
+                    throttle: None,
+                });
+            }
+        }
+
+        Ok(zones)
+    }
+
+    fn read(&self, zone: &RAPLZone) -> Result<u64, RError> {
+        let mut events = self.events.lock().unwrap();
+        let file = match events.entry((zone.core, zone.which)) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(self.open_event(zone.core, zone.which)?),
+        };
+
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf).map_err(|e| {
+            new_custom_msg(
+                IOERR,
+                format!("Failed to read perf event of `{}` (error: {})", zone.name, e),
+            )
+        })?;
+
+        Ok(u64::from_ne_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::testing::FakeRoot;
+
+    #[test]
+    fn fake_sysfs_root() {
+        let root = FakeRoot::new("perf");
+        let scale = 2f64.powi(-32);
+        root.write("type", 23);
+        root.write("cpumask", 0);
+        root.perf_event("energy-ram", "event=0x03", scale, "Joules");
+        root.perf_event("energy-pkg", "event=0x02", scale, "Joules");
+        root.perf_event("energy-cores", "event=0x01", scale, "Joules");
+        root.perf_event("energy-gpu", "event=0x04", 1.0, "Watts");
+        root.write("events/cycles", "event=0x3c");
+
+        let source = PerfSource::with_root(root.path());
+        let zones = source.domains().unwrap();
+        let names: Vec<&str> = zones.iter().map(|z| z.name.as_str()).collect();
+        assert_eq!(names, vec!["Pp0-0", "Package-0", "Dram-0"]);
+        assert_eq!(zones[1].which, 0x02);
+        assert_eq!(zones[2].which, 0x03);
+        assert!(zones.iter().all(|z| z.core == 0 && z.range == u64::MAX));
+        assert_eq!(zones[0].unit, scale);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::cores_of_package;
+use crate::error::*;
+use crate::models::RAPLZone;
+
+use super::{parse_attr, read_attr, Backend, EnergySource};
+
+const POWERCAP_ROOT: &str = "/sys/class/powercap";
+const RAPL_ZONE_PREFIX: &str = "intel-rapl:";
+
+/// RAPL energy counters read from the Linux powercap framework.
+///
+/// Every `intel-rapl:*` zone under the sysfs root exposes its counter in
+/// `energy_uj`, which wraps around at `max_energy_range_uj`. The zones are
+/// mapped onto the usual domain names:
+///
+/// | powercap name | zone          |
+/// | ------------- | ------------- |
+/// | package-N     | Package-N     |
+/// | core          | Pp0-N         |
+/// | uncore        | Pp1-N         |
+/// | dram          | Dram-N        |
+/// | psys          | Psys-N        |
+#[derive(Debug)]
+pub struct PowercapSource {
+    root: PathBuf,
+}
+
+impl PowercapSource {
+    pub fn new() -> Self {
+        Self::with_root(POWERCAP_ROOT)
+    }
+
+    /// Create a source reading zones from `root` instead of `/sys/class/powercap`.
+    pub fn with_root<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Default for PowercapSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnergySource for PowercapSource {
+    fn backend(&self) -> Backend {
+        Backend::Powercap
+    }
+
+    fn domains(&self) -> Result<Vec<RAPLZone>, RError> {
+        let entries = fs::read_dir(&self.root).map_err(|e| {
+            new_custom_msg(
+                NOT_FOUND,
+                format!("Failed to open {} (error: {})", self.root.display(), e),
+            )
+        })?;
+
+        // intel-rapl:<package>[:<subzone>]
+        let mut dirs = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|e| {
+                new_custom_msg(
+                    IOERR,
+                    format!("Failed to read {} (error: {})", self.root.display(), e),
+                )
+            })?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = file_name.strip_prefix(RAPL_ZONE_PREFIX) {
+                let id: Vec<u32> = id.split(':').filter_map(|v| v.parse().ok()).collect();
+                if !id.is_empty() {
+                    dirs.push((id, entry.path()));
+                }
+            }
+        }
+        dirs.sort();
+
+        let mut zones = vec![];
+        let mut num_psys = 0;
+        for (id, path) in dirs.iter() {
+            let name = read_attr(&path.join("name"))?;
+            let (zone_name, pkg) = match name.as_str() {
+                "psys" => {
+                    num_psys += 1;
+                    (format!("Psys-{}", num_psys - 1), 0)
+                }
+                "core" | "uncore" | "dram" => {
+                    // Subzones are numbered after the package zone they belong to.
+                    let parent = dirs.iter().find(|(pid, _)| pid[..] == id[..1]);
+                    let pkg = match parent {
+                        Some((_, parent)) => package_id(&read_attr(&parent.join("name"))?),
+                        None => None,
+                    }
+                    .unwrap_or(id[0]);
+                    let domain = match name.as_str() {
+                        "core" => "Pp0",
+                        "uncore" => "Pp1",
+                        _ => "Dram",
+                    };
+                    (format!("{}-{}", domain, pkg), pkg)
+                }
+                _ => match package_id(&name) {
+                    Some(pkg) => (format!("Package-{}", pkg), pkg),
+                    // Unknown zone, skip it.
+                    None => continue,
+                },
+            };
+
+            // The counter is read from sysfs, the core only identifies the package of the zone.
+            zones.push(RAPLZone {
+                core: cores_of_package(pkg as u16).first().copied().unwrap_or(0),
+                name: zone_name,
+                which: 0,
+                unit: 0.000001,
+                range: parse_attr(&path.join("max_energy_range_uj"))?,
+                path: Some(path.clone()),
+                throttle: None,
+            });
+        }
+
+        Ok(zones)
+    }
+
+    fn read(&self, zone: &RAPLZone) -> Result<u64, RError> {
+        let path = match zone.path.as_ref() {
+            Some(path) => path.join("energy_uj"),
+            None => {
+                crate::throw_rerr!(
+                    INVALID_VALUE,
+                    "Zone `{}` is not a powercap zone",
+                    zone.name
+                );
+            }
+        };
+
+        parse_attr(&path)
+    }
+}
+
+fn package_id(name: &str) -> Option<u32> {
+    name.strip_prefix("package-").and_then(|v| v.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::testing::FakeRoot;
+
+    #[test]
+    fn fake_sysfs_root() {
+        let root = FakeRoot::new("powercap");
+        fs::create_dir_all(root.path().join("intel-rapl")).unwrap();
+        root.powercap_zone("intel-rapl:0", "package-0", 1000, 262143328850);
+        root.powercap_zone("intel-rapl:0:0", "core", 200, 262143328850);
+        root.powercap_zone("intel-rapl:0:1", "dram", 300, 65712999613);
+        root.powercap_zone("intel-rapl:1", "package-1", 4000, 262143328850);
+        root.powercap_zone("intel-rapl:2", "psys", 5000, 262143328850);
+
+        let source = PowercapSource::with_root(root.path());
+        let zones = source.domains().unwrap();
+        let names: Vec<&str> = zones.iter().map(|z| z.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Package-0", "Pp0-0", "Dram-0", "Package-1", "Psys-0"]
+        );
+        assert_eq!(zones[2].range, 65712999613);
+        assert_eq!(source.read(&zones[2]).unwrap(), 300);
+        assert_eq!(source.unit(&zones[0]), 0.000001);
+    }
+}
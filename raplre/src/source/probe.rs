@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::*;
+use crate::limits::PowerInfo;
+use crate::models::RAPLZone;
+
+use super::{Backend, EnergySource};
+
+/// Window over which a probed counter has to advance.
+pub const PROBE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Availability of a probed RAPL domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneStatus {
+    /// The counter can be read and advanced during the probe window.
+    Available,
+    /// The counter can be read, but did not advance during the probe window.
+    Stalled,
+    /// The counter cannot be read, or always reads zero.
+    Unavailable,
+}
+
+impl std::fmt::Display for ZoneStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZoneStatus::Available => write!(f, "available"),
+            ZoneStatus::Stalled => write!(f, "stalled"),
+            ZoneStatus::Unavailable => write!(f, "unavailable"),
+        }
+    }
+}
+
+/// The result of probing a candidate RAPL domain.
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneProbe {
+    pub name: String,
+    pub status: ZoneStatus,
+    /// Energy of one counter increment, in Joules.
+    pub unit: f64,
+    /// Energy after which the counter wraps around, in Joules.
+    pub wrap_range_j: f64,
+    pub backend: Backend,
+    /// The register, sysfs zone or perf event the counter is read from.
+    pub counter: String,
+    /// The core the counter is read on.
+    pub core: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// TDP and power range of the zone, see [`crate::limits::read_power_info`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_info: Option<PowerInfo>,
+    #[serde(skip)]
+    pub zone: RAPLZone,
+}
+
+/// Probe every candidate domain of `source`.
+///
+/// Every counter is read twice, `window` apart. Counters that cannot be read or
+/// that always read zero are reported as unavailable.
+pub fn probe(source: &dyn EnergySource, window: Duration) -> Result<Vec<ZoneProbe>, RError> {
+    let zones = source.domains()?;
+    let first: Vec<Result<u64, RError>> = zones.iter().map(|zone| source.read(zone)).collect();
+    std::thread::sleep(window);
+
+    let mut out = vec![];
+    for (zone, first) in zones.into_iter().zip(first) {
+        let (status, error) = match (first, source.read(&zone)) {
+            (Ok(0), Ok(0)) => (
+                ZoneStatus::Unavailable,
+                Some("counter reads zero".to_string()),
+            ),
+            (Ok(first), Ok(second)) if first != second => (ZoneStatus::Available, None),
+            (Ok(_), Ok(_)) => (ZoneStatus::Stalled, None),
+            (Err(e), _) | (_, Err(e)) => (ZoneStatus::Unavailable, Some(e.to_string())),
+        };
+
+        let unit = source.unit(&zone);
+        out.push(ZoneProbe {
+            name: zone.name.clone(),
+            status,
+            unit,
+            wrap_range_j: source.wrap_range(&zone) as f64 * unit,
+            backend: source.backend(),
+            counter: match zone.path.as_ref().and_then(|v| v.file_name()) {
+                Some(file_name) => file_name.to_string_lossy().to_string(),
+                None => format!("{:#x}", zone.which),
+            },
+            core: zone.core,
+            error,
+            power_info: None,
+            zone,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Probe every candidate domain of `source` and keep the readable ones.
+pub fn probe_domains(source: &dyn EnergySource) -> Result<Vec<RAPLZone>, RError> {
+    Ok(probe(source, PROBE_WINDOW)?
+        .into_iter()
+        .filter(|v| v.status != ZoneStatus::Unavailable)
+        .map(|v| v.zone)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    /// Zones whose counter advances, stays constant, reads zero, or fails.
+    struct FakeSource {
+        reads: AtomicU64,
+    }
+
+    impl EnergySource for FakeSource {
+        fn backend(&self) -> Backend {
+            Backend::Msr
+        }
+
+        fn domains(&self) -> Result<Vec<RAPLZone>, RError> {
+            Ok(["Package-0", "Pp0-0", "Pp1-0", "Psys-0"]
+                .iter()
+                .enumerate()
+                .map(|(which, name)| RAPLZone {
+                    name: name.to_string(),
+                    which: which as u32,
+                    unit: 0.5,
+                    range: u32::MAX as u64,
+                    ..Default::default()
+                })
+                .collect())
+        }
+
+        fn read(&self, zone: &RAPLZone) -> Result<u64, RError> {
+            match zone.which {
+                0 => Ok(1000 + self.reads.fetch_add(1, Ordering::Relaxed)),
+                1 => Ok(42),
+                2 => Ok(0),
+                _ => {
+                    crate::throw_rerr!(IOERR, "Failed to read {}", zone.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zone_status() {
+        let source = FakeSource {
+            reads: AtomicU64::new(0),
+        };
+        let probes = probe(&source, Duration::ZERO).unwrap();
+        let status: Vec<(&str, ZoneStatus)> =
+            probes.iter().map(|v| (v.name.as_str(), v.status)).collect();
+        assert_eq!(
+            status,
+            vec![
+                ("Package-0", ZoneStatus::Available),
+                ("Pp0-0", ZoneStatus::Stalled),
+                ("Pp1-0", ZoneStatus::Unavailable),
+                ("Psys-0", ZoneStatus::Unavailable),
+            ]
+        );
+        assert_eq!(probes[0].error, None);
+        assert_eq!(probes[2].error.as_deref(), Some("counter reads zero"));
+        assert!(probes[3].error.is_some());
+        assert_eq!(probes[0].wrap_range_j, u32::MAX as f64 * 0.5);
+        assert_eq!(probes[1].counter, "0x1");
+
+        let names: Vec<String> = probe_domains(&source)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        assert_eq!(names, vec!["Package-0", "Pp0-0"]);
+    }
+}
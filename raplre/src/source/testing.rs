@@ -0,0 +1,56 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A temporary directory standing in for a sysfs tree, removed when dropped.
+pub(crate) struct FakeRoot(PathBuf);
+
+impl FakeRoot {
+    /// An empty tree, named after `name` and the process, so that tests can
+    /// run in parallel.
+    pub(crate) fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("raplre-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Self(root)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write `value` to the attribute `file`, creating its directory.
+    pub(crate) fn write<V: Display>(&self, file: &str, value: V) {
+        let path = self.0.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{}\n", value)).unwrap();
+    }
+
+    /// Read the attribute `file`, without the trailing newline.
+    pub(crate) fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.0.join(file))
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    /// A powercap zone in `dir`.
+    pub(crate) fn powercap_zone(&self, dir: &str, name: &str, energy: u64, range: u64) {
+        self.write(&format!("{}/name", dir), name);
+        self.write(&format!("{}/energy_uj", dir), energy);
+        self.write(&format!("{}/max_energy_range_uj", dir), range);
+    }
+
+    /// An event of the `power` perf PMU.
+    pub(crate) fn perf_event(&self, event: &str, desc: &str, scale: f64, unit: &str) {
+        self.write(&format!("events/{}", event), desc);
+        self.write(&format!("events/{}.scale", event), scale);
+        self.write(&format!("events/{}.unit", event), unit);
+    }
+}
+
+impl Drop for FakeRoot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+// Two-sided 95% quantiles of Student's t distribution, for 1 to 30 degrees of freedom.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+// Beyond 30 degrees of freedom, for 40, 60 and 120.
+const T_95_LARGE: [(usize, f64); 3] = [(40, 2.021), (60, 2.000), (120, 1.980)];
+
+/// The two-sided 95% quantile of Student's t distribution for `df` degrees
+/// of freedom. Between the tabulated values, the one of the next smaller df
+/// is used, which gives a slightly wider interval.
+fn t_95(df: usize) -> f64 {
+    match T_95.get(df.max(1) - 1) {
+        Some(t) => *t,
+        None => T_95_LARGE
+            .iter()
+            .rev()
+            .find(|(large_df, _)| *large_df <= df)
+            .map(|(_, t)| *t)
+            .unwrap_or(T_95[T_95.len() - 1]),
+    }
+}
+
+/// Descriptive statistics of a metric over the iterations of a batch.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Number of iterations.
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation.
+    pub std: f64,
+    /// Coefficient of variation, `std / mean`.
+    pub cv: f64,
+    pub min: f64,
+    pub max: f64,
+    /// 95% confidence interval of the mean, from Student's t distribution.
+    /// Needs at least two iterations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci95_low: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci95_high: Option<f64>,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return Self::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median = match n % 2 {
+            0 => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+            _ => sorted[n / 2],
+        };
+
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let mut stats = Self {
+            n,
+            mean,
+            median,
+            min: sorted[0],
+            max: sorted[n - 1],
+            ..Default::default()
+        };
+        if n < 2 {
+            return stats;
+        }
+
+        let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        stats.std = var.sqrt();
+        stats.cv = match mean {
+            mean if mean != 0.0 => stats.std / mean.abs(),
+            _ => 0.0,
+        };
+
+        let t = t_95(n - 1);
+        let half_width = t * stats.std / (n as f64).sqrt();
+        stats.ci95_low = Some(mean - half_width);
+        stats.ci95_high = Some(mean + half_width);
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_stats() {
+        let stats = Stats::from_samples(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.n, 8);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.median, 4.5);
+        assert!((stats.std - 2.138).abs() < 1e-3);
+        assert!((stats.cv - 0.4276).abs() < 1e-3);
+        // t(7) = 2.365
+        let half_width = 2.365 * stats.std / 8f64.sqrt();
+        assert!((stats.ci95_high.unwrap() - 5.0 - half_width).abs() < 1e-9);
+        assert!((5.0 - stats.ci95_low.unwrap() - half_width).abs() < 1e-9);
+
+        let single = Stats::from_samples(&[3.0]);
+        assert_eq!((single.mean, single.median, single.std), (3.0, 3.0, 0.0));
+        assert_eq!(single.ci95_low, None);
+        assert_eq!(Stats::from_samples(&[]).n, 0);
+    }
+
+    #[test]
+    fn t_quantiles() {
+        assert_eq!(t_95(1), 12.706);
+        assert_eq!(t_95(30), 2.042);
+        assert_eq!(t_95(39), 2.042);
+        assert_eq!(t_95(40), 2.021);
+        assert_eq!(t_95(59), 2.021);
+        assert_eq!(t_95(60), 2.000);
+        assert_eq!(t_95(120), 1.980);
+        assert_eq!(t_95(1000), 1.980);
+    }
+}
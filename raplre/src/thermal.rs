@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+use crate::arch::{detect_vendor, Vendor};
+use crate::common::{get_core_of_package, physical_cores_of_package};
+use crate::error::*;
+
+pub(crate) const IA32_THERM_STATUS: u32 = 0x19C;
+pub(crate) const IA32_TEMPERATURE_TARGET: u32 = 0x1A2;
+pub(crate) const IA32_PACKAGE_THERM_STATUS: u32 = 0x1B1;
+
+/// A temperature reading of a package, in °C. `None` when the sensor reports
+/// no valid reading.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermalSample {
+    pub package: Option<f64>,
+    /// The hottest core of the package, if per-core temperatures are read.
+    pub core_max: Option<f64>,
+}
+
+/// The digital thermal sensors of a package.
+///
+/// The sensors report the distance to TjMax, the temperature at which the
+/// package starts thermal throttling, which is read from `IA32_TEMPERATURE_TARGET`.
+#[derive(Debug, Clone)]
+pub struct PackageThermal {
+    pub pkg: u16,
+    /// Maximum junction temperature, in °C.
+    pub tj_max: f64,
+    core: u32,
+    // physical cores whose sensor is read, empty unless per-core temperatures are read
+    cores: Vec<u32>,
+}
+
+impl PackageThermal {
+    /// Open the sensors of package `pkg`, and of all its cores with `per_core`.
+    pub fn open(pkg: u16, per_core: bool) -> Result<Self, RError> {
+        if detect_vendor(pkg)? != Vendor::Intel {
+            crate::throw_rerr!(
+                NOT_ALLOWED,
+                "Digital thermal sensors are only supported on Intel CPUs"
+            );
+        }
+
+        let core = match get_core_of_package(pkg) {
+            Some(core) => core,
+            None => {
+                crate::throw_rerr!(NOT_FOUND, "No enabled core in package {}", pkg);
+            }
+        };
+
+        // Temperature Target (23:16), in °C.
+        let raw = crate::cpuid::read_msr(core, IA32_TEMPERATURE_TARGET)?;
+        let tj_max = ((raw >> 16) & 0xFF) as f64;
+        if tj_max == 0.0 {
+            crate::throw_rerr!(NOT_FOUND, "No TjMax reported by package {}", pkg);
+        }
+
+        Ok(Self {
+            pkg,
+            tj_max,
+            core,
+            cores: if per_core {
+                physical_cores_of_package(pkg)
+            } else {
+                vec![]
+            },
+        })
+    }
+
+    pub fn read(&self) -> Result<ThermalSample, RError> {
+        let package = self.decode(crate::cpuid::read_msr(self.core, IA32_PACKAGE_THERM_STATUS)?);
+
+        let mut core_max = None;
+        for core in self.cores.iter() {
+            if let Some(temp) = self.decode(crate::cpuid::read_msr(*core, IA32_THERM_STATUS)?) {
+                core_max = Some(core_max.map_or(temp, |max: f64| max.max(temp)));
+            }
+        }
+
+        Ok(ThermalSample { package, core_max })
+    }
+
+    /// Decode the Digital Readout (22:16) of a thermal status register, in °C
+    /// below TjMax. The readout is only valid with the Reading Valid bit (31) set.
+    fn decode(&self, raw: u64) -> Option<f64> {
+        match raw & (1 << 31) {
+            0 => None,
+            _ => Some(self.tj_max - ((raw >> 16) & 0x7F) as f64),
+        }
+    }
+}
+
+/// Open the thermal sensors of every package. Packages whose sensors cannot
+/// be read, e.g. without MSR access or on AMD CPUs, are skipped.
+pub(crate) fn open_thermal(per_core: bool) -> Vec<PackageThermal> {
+    let mut out = vec![];
+    for pkg in 0..cpu::topology().max_num_packages() {
+        match PackageThermal::open(pkg, per_core) {
+            Ok(thermal) => out.push(thermal),
+            Err(e) => {
+                crate::ignore_rerr!(e);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_readout() {
+        let thermal = PackageThermal {
+            pkg: 0,
+            tj_max: 100.0,
+            core: 0,
+            cores: vec![],
+        };
+        assert_eq!(thermal.decode(0x8838_0000), Some(44.0));
+        assert_eq!(thermal.decode(0x0838_0000), None);
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Achieved sampling interval of a [`Ticker`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntervalStats {
+    /// Requested interval, in seconds.
+    pub target_s: f64,
+    /// Number of deadlines a sample was taken on.
+    pub samples: u64,
+    /// Deadlines that passed without a sample, because the previous sample
+    /// took longer than the interval.
+    pub missed: u64,
+    /// Delay between a deadline and the sample taken on it, in seconds.
+    pub jitter_mean_s: f64,
+    pub jitter_std_s: f64,
+    pub jitter_max_s: f64,
+    /// Time between two consecutive samples, in seconds.
+    pub interval_mean_s: f64,
+    pub interval_min_s: f64,
+    pub interval_max_s: f64,
+}
+
+/// A periodic schedule with absolute deadlines.
+///
+/// The deadlines are `start + k * period`, so the time spent taking a sample
+/// or a late wakeup does not shift the following samples. Deadlines that have
+/// already passed are skipped and counted as missed.
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    period: Duration,
+    next: Instant,
+    last: Instant,
+    samples: u64,
+    missed: u64,
+    jitter_sum: f64,
+    jitter_sum_sq: f64,
+    jitter_max: f64,
+    interval_sum: f64,
+    interval_min: f64,
+    interval_max: f64,
+}
+
+impl Ticker {
+    /// Start a schedule whose first deadline is one `period` from now.
+    pub fn new(period: Duration) -> Self {
+        Self::start_at(period, Instant::now())
+    }
+
+    fn start_at(period: Duration, now: Instant) -> Self {
+        Self {
+            period,
+            next: now + period,
+            last: now,
+            samples: 0,
+            missed: 0,
+            jitter_sum: 0.0,
+            jitter_sum_sq: 0.0,
+            jitter_max: 0.0,
+            interval_sum: 0.0,
+            interval_min: f64::MAX,
+            interval_max: 0.0,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Time left until the next deadline, zero if it has already passed.
+    pub fn remaining(&self) -> Duration {
+        self.next.saturating_duration_since(Instant::now())
+    }
+
+    /// Sleep until the next deadline, then [`Ticker::tick`].
+    pub fn wait(&mut self) {
+        std::thread::sleep(self.remaining());
+        self.tick();
+    }
+
+    /// Record a sample taken now on the current deadline, and move on to the
+    /// next deadline that has not passed yet.
+    pub fn tick(&mut self) {
+        self.tick_at(Instant::now());
+    }
+
+    fn tick_at(&mut self, now: Instant) {
+        let jitter = now.saturating_duration_since(self.next).as_secs_f64();
+        self.jitter_sum += jitter;
+        self.jitter_sum_sq += jitter * jitter;
+        self.jitter_max = self.jitter_max.max(jitter);
+
+        let interval = now.duration_since(self.last).as_secs_f64();
+        self.interval_sum += interval;
+        self.interval_min = self.interval_min.min(interval);
+        self.interval_max = self.interval_max.max(interval);
+
+        self.samples += 1;
+        self.last = now;
+
+        if self.period.is_zero() {
+            self.next = now;
+            return;
+        }
+        self.next += self.period;
+        while self.next <= now {
+            self.next += self.period;
+            self.missed += 1;
+        }
+    }
+
+    pub fn stats(&self) -> IntervalStats {
+        let mut stats = IntervalStats {
+            target_s: self.period.as_secs_f64(),
+            samples: self.samples,
+            missed: self.missed,
+            ..Default::default()
+        };
+        if self.samples == 0 {
+            return stats;
+        }
+
+        let n = self.samples as f64;
+        stats.jitter_mean_s = self.jitter_sum / n;
+        stats.jitter_std_s = (self.jitter_sum_sq / n - stats.jitter_mean_s.powi(2))
+            .max(0.0)
+            .sqrt();
+        stats.jitter_max_s = self.jitter_max;
+        stats.interval_mean_s = self.interval_sum / n;
+        stats.interval_min_s = self.interval_min;
+        stats.interval_max_s = self.interval_max;
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn deadlines_do_not_drift() {
+        let start = Instant::now();
+        let mut ticker = Ticker::start_at(PERIOD, start);
+
+        // Every sample is taken 2 ms late, the deadlines stay on the grid.
+        for k in 1..=5u32 {
+            ticker.tick_at(start + PERIOD * k + Duration::from_millis(2));
+            assert_eq!(ticker.next, start + PERIOD * (k + 1));
+        }
+
+        let stats = ticker.stats();
+        assert_eq!((stats.samples, stats.missed), (5, 0));
+        assert!((stats.jitter_mean_s - 0.002).abs() < 1e-9);
+        assert!(stats.jitter_std_s < 1e-6);
+        assert!((stats.interval_min_s - 0.020).abs() < 1e-9);
+        assert!((stats.interval_max_s - 0.022).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missed_deadlines_are_skipped() {
+        let start = Instant::now();
+        let mut ticker = Ticker::start_at(PERIOD, start);
+
+        // A stall of 3.5 periods: the sample is taken on the first deadline,
+        // the second and third have passed and are skipped.
+        ticker.tick_at(start + PERIOD * 7 / 2);
+        assert_eq!(ticker.next, start + PERIOD * 4);
+        assert_eq!(ticker.stats().missed, 2);
+
+        // No burst of catch-up samples, the next one is on the fourth deadline.
+        ticker.tick_at(start + PERIOD * 4);
+        assert_eq!(ticker.next, start + PERIOD * 5);
+        let stats = ticker.stats();
+        assert_eq!((stats.samples, stats.missed), (2, 2));
+        assert!((stats.jitter_max_s - 0.050).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_period() {
+        let start = Instant::now();
+        let mut ticker = Ticker::start_at(Duration::ZERO, start);
+        ticker.tick_at(start + PERIOD);
+        assert_eq!(ticker.next, start + PERIOD);
+        assert_eq!(ticker.stats().missed, 0);
+    }
+}
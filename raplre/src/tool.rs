@@ -1,205 +1,847 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Child;
 use std::process::Command;
+use std::process::ExitStatus;
 use std::process::Stdio;
-use std::sync::mpsc;
+use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 use std::time::SystemTime;
 use std::u32;
 
-use crate::common;
-use crate::error::*;
-use crate::logger;
-use crate::models;
-use crate::models::RAPLData;
-use crate::models::StatData;
-use crate::task;
+use raplre::*;
+
+use crate::display;
+
+// How often the benchmarked program is checked for exit (ms)
+const CHILD_POLL_DELAY: u64 = 10;
+const BENCHMARK_TOOL: &str = "benchmark";
 
 pub(crate) fn live_measurement(
-    poll_delay: u32,
+    session_config: &SessionConfig,
     run_time_limit: Option<u32>,
     name: Option<&String>,
     dir: Option<&PathBuf>,
 ) -> Result<(), RError> {
-    let sleep = std::time::Duration::from_millis(poll_delay as u64);
-    let mut zones = common::setup_rapl_data();
-    assert!(zones.len() != 0);
     let system_start_time = SystemTime::now();
-    let start_time = Instant::now();
     let run_time_limit = run_time_limit.unwrap_or(0) as u64;
-    let mut prev_time = start_time;
-    #[allow(unused_assignments)]
-    let mut now = start_time;
     const TOOL_NAME: &'static str = "live";
 
     let output_file = match dir {
-        Some(dir) => dir.join(common::create_log_file_name(
+        Some(dir) => dir.join(create_log_file_name(
             name.unwrap_or(&"default".to_string()),
             TOOL_NAME,
             Some(system_start_time),
         )),
-        None => PathBuf::from(common::create_log_file_name(
+        None => PathBuf::from(create_log_file_name(
             name.unwrap_or(&"default".to_string()),
             TOOL_NAME,
             None,
         )),
     };
 
+    let mut session = match Session::new(SessionConfig {
+        output_file,
+        ..session_config.clone()
+    }) {
+        Ok(session) => session,
+        Err(e) => {
+            display::terminate();
+            return Err(e);
+        }
+    };
+    if let Err(e) = session.setup_sampler_thread() {
+        display::terminate();
+        return Err(e);
+    }
+    let start_time = session.start_time();
+
     loop {
-        now = Instant::now();
-        common::update_measurements(
-            &mut zones,
-            now,
-            start_time,
-            prev_time,
-            &output_file,
-            None,
-            false,
-        );
-        prev_time = now;
+        let zones = match session.sample() {
+            Ok(zones) => zones,
+            Err(e) => {
+                display::terminate();
+                return Err(e);
+            }
+        };
 
         ncurses::clear();
         ncprint!("Press 'q' to quit\n");
-        print_result_line!(&zones, true);
+        print_result_line!(zones, true);
 
-        if ncurses::getch() == common::KEY_CODE_EXIT {
+        if ncurses::getch() == display::KEY_CODE_EXIT {
             ncurses::endwin();
             break;
         }
 
-        if common::should_terminate(run_time_limit, now, start_time) {
-            common::terminate();
+        if display::should_terminate(run_time_limit, Instant::now(), start_time) {
+            display::terminate();
             break;
         }
 
-        std::thread::sleep(sleep);
-
-        // std::thread::sleep(std::time::Duration::from_secs(100000));
+        session.wait();
     }
 
+    let mut summary = create_run_summary(
+        name.unwrap_or(&"default".to_string()),
+        TOOL_NAME,
+        session_config.backend,
+        system_start_time,
+        start_time.elapsed(),
+        session.zones(),
+    );
+    summary.interval = Some(session.interval_stats());
+    summary.sampler = Some(session.overhead());
+    log_run_summary(&create_summary_file_name(session.output_file()), &summary)?;
+
     print_headers!();
-    print_result_line!(&zones);
+    print_result_line!(session.zones());
     println!();
 
     Ok(())
 }
 
+/// Options of the `benchmark` tool.
+pub(crate) struct BenchmarkConfig {
+    pub runner: Option<PathBuf>,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub count: u32,
+    pub warmup: u32,
+    pub smooth: bool,
+    pub interval: u32,
+    pub caps: Vec<PowerCap>,
+    pub cap_backend: CapBackend,
+    pub cap_dry_run: bool,
+    pub attribute: bool,
+    pub cgroup: Option<PathBuf>,
+    pub output: ChildOutput,
+    pub allow_failure: bool,
+    pub timeout: Option<Duration>,
+    pub kill_after: Duration,
+    pub placement: Placement,
+    pub sampler_off_cpus: bool,
+}
+
+/// Where the stdout and stderr of the benchmarked program go.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChildOutput {
+    /// Discarded.
+    #[default]
+    Null,
+    /// Passed through to the terminal.
+    Inherit,
+    /// Written to `.stdout` and `.stderr` files next to the .csv output of the iteration.
+    Capture,
+}
+
+impl ChildOutput {
+    /// The stdout and stderr of a program whose iteration writes to `output_file`.
+    fn stdio(&self, output_file: &Path) -> Result<(Stdio, Stdio), RError> {
+        let create = |extension: &str| {
+            let file = output_file.with_extension(extension);
+            File::create(&file).map_err(|e| {
+                new_custom_msg(
+                    IOERR,
+                    format!("Failed to create {} (error: {})", file.display(), e),
+                )
+            })
+        };
+
+        Ok(match self {
+            ChildOutput::Null => (Stdio::null(), Stdio::null()),
+            ChildOutput::Inherit => (Stdio::inherit(), Stdio::inherit()),
+            ChildOutput::Capture => (create("stdout")?.into(), create("stderr")?.into()),
+        })
+    }
+}
+
+impl Display for ChildOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChildOutput::Null => write!(f, "null"),
+            ChildOutput::Inherit => write!(f, "inherit"),
+            ChildOutput::Capture => write!(f, "capture"),
+        }
+    }
+}
+
+impl FromStr for ChildOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "null" => Ok(ChildOutput::Null),
+            "inherit" => Ok(ChildOutput::Inherit),
+            "capture" => Ok(ChildOutput::Capture),
+            _ => Err(format!(
+                "unknown child output `{}` (expected one of: null, inherit, capture)",
+                s
+            )),
+        }
+    }
+}
+
+/// A cgroup created for a benchmark run, removed when dropped.
+struct ChildCgroup(PathBuf);
+
+impl Drop for ChildCgroup {
+    fn drop(&mut self) {
+        if let Err(e) = remove_cgroup(&self.0) {
+            eprintln!("WARNING: {}, a process may still run in it", e);
+        }
+    }
+}
+
 pub(crate) fn do_benchmarks(
-    poll_delay: u32,
+    session_config: &SessionConfig,
     name: Option<&String>,
     dir: Option<&PathBuf>,
     isolate: Option<&PathBuf>,
-    runner: Option<PathBuf>,
-    program: PathBuf,
-    args: Vec<String>,
-    count: u32,
-    smooth: bool,
-    interval: u32,
+    config: &BenchmarkConfig,
 ) -> Result<(), RError> {
+    let count = config.count;
+    let warmup = config.warmup;
+    let interval = config.interval;
     let sleep = Duration::from_secs(interval as u64);
     let raw_name = name.map(|v| v.clone()).unwrap_or(format!("default"));
-    let mut name = raw_name.clone();
-    if count > 1 {
-        name = format!("{}_iter1", raw_name);
+    if config.attribute || config.cgroup.is_some() {
+        check_attribution_isolate(isolate)?;
+    }
+
+    let mut session_config = session_config.clone();
+    if config.sampler_off_cpus {
+        session_config.sampler_cpu = Some(config.placement.free_cpu(session_config.sampler_cpu)?);
     }
 
-    for i in 0..count {
-        if count > 1 {
-            println!("Running benchmark iteration {}", i + 1);
-            name = format!("{}_iter{}", raw_name, i);
+    let control = if config.caps.is_empty() {
+        None
+    } else {
+        Some(open_cap_control(config.cap_backend)?)
+    };
+    // The child and the power limits are cleaned up before exiting on Ctrl-C.
+    crate::signal::catch_termination();
+
+    // Warm-up iterations run first, and are left out of the statistics of the batch.
+    let batch_start_time = SystemTime::now();
+    let mut iterations = vec![];
+    let mut result = Ok(());
+    for i in 0..warmup + count {
+        let is_warmup = i < warmup;
+        let name = if is_warmup {
+            format!("{}_warmup{}", raw_name, i + 1)
+        } else if warmup + count > 1 {
+            format!("{}_iter{}", raw_name, i - warmup + 1)
+        } else {
+            raw_name.clone()
+        };
+        if warmup + count > 1 {
+            if is_warmup {
+                println!("Running benchmark warm-up iteration {}", i + 1);
+            } else {
+                println!("Running benchmark iteration {}", i - warmup + 1);
+            }
         }
 
-        benchmark(
-            poll_delay,
-            smooth,
+        match run_iteration(
+            &session_config,
             &name,
-            dir.clone(),
-            isolate.clone(),
-            runner.as_ref(),
-            &program,
-            &args,
-        )?;
+            dir,
+            isolate,
+            config,
+            control.as_deref(),
+        ) {
+            Ok(summary) => iterations.push(IterationSummary {
+                warmup: is_warmup,
+                ..IterationSummary::from(&summary)
+            }),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
 
-        if interval > 0 && i + 1 < count {
+        if interval > 0 && i + 1 < warmup + count {
             println!("Sleep for {} seconds before next benchmark run", interval);
-            thread::sleep(sleep);
+            // Sleep in slices, so that a signal stops the batch right away.
+            let deadline = Instant::now() + sleep;
+            while crate::signal::received().is_none() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(CHILD_POLL_DELAY));
+            }
+            if let Some(sig) = crate::signal::received() {
+                result = Err(new_custom_msg(
+                    NOT_ALLOWED,
+                    format!("Benchmark interrupted by signal {}", sig),
+                ));
+                break;
+            }
         }
     }
 
-    Ok(())
+    // Also summarize the iterations that completed before a failure.
+    if !iterations.is_empty() {
+        let batch = create_batch_summary(&raw_name, BENCHMARK_TOOL, iterations);
+        // The start time keeps the summary of an earlier batch of the same name.
+        let batch_file = PathBuf::from(create_log_file_name(
+            &raw_name,
+            BENCHMARK_TOOL,
+            Some(batch_start_time),
+        ))
+        .with_extension("batch.json");
+        log_batch_summary(
+            &match dir {
+                Some(dir) => dir.join(batch_file),
+                None => batch_file,
+            },
+            &batch,
+        )?;
+        if warmup + count > 1 {
+            print_batch(&batch);
+        }
+    }
+
+    result
+}
+
+/// Run one iteration of a batch under the power caps of `config`.
+fn run_iteration(
+    session_config: &SessionConfig,
+    name: &String,
+    dir: Option<&PathBuf>,
+    isolate: Option<&PathBuf>,
+    config: &BenchmarkConfig,
+    control: Option<&dyn CapControl>,
+) -> Result<RunSummary, RError> {
+    let guard = match control {
+        Some(control) => Some(CapGuard::apply(control, &config.caps, config.cap_dry_run)?),
+        None => None,
+    };
+    let caps = guard
+        .as_ref()
+        .map(|g| g.applied().to_vec())
+        .unwrap_or_default();
+    for cap in caps.iter() {
+        println!(
+            "{} power cap of {}: {:.3} W / {:.3} s (was {:.3} W / {:.3} s){}",
+            if cap.dry_run {
+                "Would apply"
+            } else {
+                "Applied"
+            },
+            cap.zone,
+            cap.applied.power_w,
+            cap.applied.time_window_s,
+            cap.original.power_w,
+            cap.original.time_window_s,
+            if cap.dry_run { " [dry run]" } else { "" },
+        );
+    }
+
+    let res = benchmark(
+        session_config,
+        config.smooth,
+        name,
+        dir,
+        isolate,
+        config,
+        caps,
+    );
+
+    // Restore even if the run failed, but report the first error.
+    let restored = match guard {
+        Some(guard) => guard.restore(),
+        None => Ok(()),
+    };
+    let summary = res?;
+    restored?;
+
+    Ok(summary)
+}
+
+/// Print the statistics of the measured iterations of a batch.
+fn print_batch(batch: &BatchSummary) {
+    let ci = |stats: &Stats| match (stats.ci95_low, stats.ci95_high) {
+        (Some(low), Some(high)) => format!(", 95% CI {:.3}..{:.3}", low, high),
+        _ => String::new(),
+    };
+
+    println!(
+        "Batch of {} iterations ({} warm-up excluded), duration {:.3} s (median {:.3}, std {:.3}, cv {:.1} %{})",
+        batch.duration_s.n,
+        batch.warmup,
+        batch.duration_s.mean,
+        batch.duration_s.median,
+        batch.duration_s.std,
+        batch.duration_s.cv * 100.0,
+        ci(&batch.duration_s)
+    );
+    for zone in batch.zones.iter() {
+        println!(
+            "{}: {:.3} J (median {:.3}, std {:.3}, cv {:.1} %{}), {:.3} W (median {:.3}, std {:.3}, cv {:.1} %{})",
+            zone.zone_name,
+            zone.energy_j.mean,
+            zone.energy_j.median,
+            zone.energy_j.std,
+            zone.energy_j.cv * 100.0,
+            ci(&zone.energy_j),
+            zone.avg_watt.mean,
+            zone.avg_watt.median,
+            zone.avg_watt.std,
+            zone.avg_watt.cv * 100.0,
+            ci(&zone.avg_watt)
+        );
+    }
 }
 
 fn benchmark(
-    poll_delay: u32,
+    session_config: &SessionConfig,
     smooth: bool,
     name: &String,
     dir: Option<&PathBuf>,
     isolate_file: Option<&PathBuf>,
-    runner: Option<&PathBuf>,
-    program: &PathBuf,
-    args: &Vec<String>,
-) -> Result<(), RError> {
-    let isolate_map = common::read_isolated_data(isolate_file);
-    let start_time = Instant::now();
-    // let iteration_start_time = SystemTime::now();
-    const TOOL_NAME: &'static str = "benchmark";
+    config: &BenchmarkConfig,
+    caps: Vec<AppliedCap>,
+) -> Result<RunSummary, RError> {
+    let isolate_map = read_isolated_data(isolate_file)?;
+    let iteration_start_time = SystemTime::now();
 
     let output_file = match dir {
-        Some(dir) => dir.join(common::create_log_file_name(name, TOOL_NAME, None)),
-        None => PathBuf::from(common::create_log_file_name(name, TOOL_NAME, None)),
+        Some(dir) => dir.join(create_log_file_name(name, BENCHMARK_TOOL, None)),
+        None => PathBuf::from(create_log_file_name(name, BENCHMARK_TOOL, None)),
     };
+    let summary_file = create_summary_file_name(&output_file);
+    let (stdout, stderr) = config.output.stdio(&output_file)?;
 
-    let (send, recv) = mpsc::channel();
-
-    let thr = task::spawn_measurement_thread(
-        start_time,
-        recv,
-        poll_delay,
-        output_file.clone(),
+    let start_time = Instant::now();
+    let mut session = Session::new(SessionConfig {
+        output_file,
         smooth,
         isolate_map,
-    );
+        ..session_config.clone()
+    })?;
+    let target = ProcessTarget::default();
+    let cgroup = match config.cgroup.as_ref() {
+        Some(parent) => {
+            let cgroup = ChildCgroup(create_child_cgroup(
+                parent,
+                &format!("raplre-{}", std::process::id()),
+            )?);
+            session.set_attribution(Box::new(CgroupAttribution::open(&cgroup.0)?))?;
+            Some(cgroup)
+        }
+        None => {
+            if config.attribute {
+                session.set_attribution(Box::new(ProcessAttribution::new(target.clone())?))?;
+            }
+            None
+        }
+    };
+    let handle = session.spawn()?;
 
-    match runner {
+    let mut cmd = match config.runner.as_ref() {
         Some(r) => {
-            let _out = Command::new(r)
-                .arg(program)
-                .args(args)
-                .stderr(Stdio::null())
-                .stdout(Stdio::null())
-                .output()
-                .expect("Failed to execute command");
+            let mut cmd = Command::new(r);
+            cmd.arg(&config.program);
+            cmd
         }
-        None => {
-            let _out = Command::new(program)
-                .args(args)
-                .stderr(Stdio::null())
-                .stdout(Stdio::null())
-                .output()
-                .expect("Failed to execute command");
+        None => Command::new(&config.program),
+    };
+    let procs = match cgroup.as_ref() {
+        Some(cgroup) => {
+            let file = cgroup.0.join("cgroup.procs");
+            Some(File::options().write(true).open(&file).map_err(|e| {
+                new_custom_msg(
+                    IOERR,
+                    format!("Failed to open {} (error: {})", file.display(), e),
+                )
+            })?)
+        }
+        None => None,
+    };
+    if let Some(procs) = procs.as_ref() {
+        let fd = procs.as_raw_fd();
+        // Move the child into the cgroup before it executes the program, writing
+        // "0" moves the writing process.
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
         }
     }
+    config.placement.apply_to(&mut cmd);
+    // The child leads its own process group, so that signals reach all its descendants.
+    let mut child = cmd
+        .process_group(0)
+        .args(&config.args)
+        .stdout(stdout)
+        .stderr(stderr)
+        .spawn()
+        .map_err(|e| {
+            new_custom_msg(
+                IOERR,
+                format!(
+                    "Failed to execute {} (error: {})",
+                    config.runner.as_ref().unwrap_or(&config.program).display(),
+                    e
+                ),
+            )
+        })?;
+    target.set(child.id());
+    let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
 
-    send.send(common::THREAD_KILL)
-        .expect("Failed to concact measurement thread");
-    thr.join()
-        .expect("Failed to wait for measurement thread to finish");
+    // The child is stopped and the session joined whatever happens, so that the
+    // final sample and the summary are written before an error is returned.
+    let mut run_status = RunStatus::Completed;
+    let waited = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!(
+                    "WARNING:Failed to wait for benchmark (error: {}), stopping it",
+                    e
+                );
+                run_status = RunStatus::Interrupted;
+                let _ = stop_child(&mut child, libc::SIGTERM, config.kill_after);
+                break Err(new_custom_msg(
+                    IOERR,
+                    format!("Failed to wait for benchmark (error: {})", e),
+                ));
+            }
+        }
 
-    let new_zones = common::get_last_measurement_from(output_file);
+        if let Some(sig) = crate::signal::received() {
+            eprintln!("Interrupted by signal {}, stopping the benchmark", sig);
+            run_status = RunStatus::Interrupted;
+            break stop_child(&mut child, sig, config.kill_after);
+        }
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                eprintln!(
+                    "WARNING:{} timed out after {:.3} s, stopping it",
+                    config.program.display(),
+                    config.timeout.unwrap_or_default().as_secs_f64()
+                );
+                run_status = RunStatus::TimedOut;
+                break stop_child(&mut child, libc::SIGTERM, config.kill_after);
+            }
+        }
+
+        thread::sleep(Duration::from_millis(CHILD_POLL_DELAY));
+    };
+
+    let session = handle.join()?;
+    let duration = start_time.elapsed();
+    let new_zones = session.zones().to_vec();
+
+    let mut summary = create_run_summary(
+        name,
+        BENCHMARK_TOOL,
+        session_config.backend,
+        iteration_start_time,
+        duration,
+        &new_zones,
+    );
+    summary.caps = caps;
+    summary.interval = Some(session.interval_stats());
+    summary.sampler = Some(session.overhead());
+    summary.attribution = session.attribution_model();
+    summary.exit = waited.as_ref().ok().map(|status| ChildExit::from(*status));
+    summary.status = run_status;
+    if !config.placement.is_empty() {
+        summary.placement = Some(config.placement.clone());
+    }
+    log_run_summary(&summary_file, &summary)?;
 
     print_headers!(false);
     print_result_line!(&new_zones);
     println!();
+    print_attributed(&new_zones, "the program");
+    for zone in new_zones.iter().filter(|z| z.zone.throttle.is_some()) {
+        println!(
+            "{} throttled by its power limits for {:.3} s",
+            zone.zone_name, zone.throttled_s
+        );
+    }
+    for zone in new_zones.iter().filter(|z| z.gaps > 0) {
+        eprintln!(
+            "WARNING:{} counter may have wrapped around more than once in {} sample intervals, its energy may be undercounted",
+            zone.zone_name, zone.gaps
+        );
+    }
+
+    let exit = ChildExit::from(waited?);
+    if run_status == RunStatus::Interrupted {
+        raplre::throw_rerr!(
+            NOT_ALLOWED,
+            "Benchmark interrupted by signal {}",
+            crate::signal::received().unwrap_or_default()
+        );
+    }
+    if !exit.success() {
+        if !config.allow_failure {
+            raplre::throw_rerr!(
+                NOT_ALLOWED,
+                "{} failed with {}",
+                config.program.display(),
+                exit
+            );
+        }
+        eprintln!("WARNING:{} failed with {}", config.program.display(), exit);
+    }
+
+    Ok(summary)
+}
+
+/// Send `sig` to the process group of `child`, and SIGKILL the group if the
+/// child is still running after `kill_after`.
+fn stop_child(child: &mut Child, sig: i32, kill_after: Duration) -> Result<ExitStatus, RError> {
+    let pgid = child.id() as libc::pid_t;
+    unsafe {
+        libc::kill(-pgid, sig);
+    }
+
+    let deadline = Instant::now() + kill_after;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(status),
+            Ok(None) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(CHILD_POLL_DELAY));
+            }
+            Ok(None) => {
+                eprintln!(
+                    "WARNING:process group {} still running {:.3} s after signal {}, sending SIGKILL",
+                    pgid,
+                    kill_after.as_secs_f64(),
+                    sig
+                );
+                break;
+            }
+            Err(e) => {
+                eprintln!(
+                    "WARNING:Failed to wait for process group {} (error: {}), sending SIGKILL",
+                    pgid, e
+                );
+                break;
+            }
+        }
+    }
+
+    unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+    }
+    child.wait().map_err(|e| {
+        new_custom_msg(
+            IOERR,
+            format!("Failed to wait for benchmark (error: {})", e),
+        )
+    })
+}
+
+/// Attribution only splits the energy above idle power, fail before running
+/// anything without an isolate file.
+fn check_attribution_isolate(isolate: Option<&PathBuf>) -> Result<(), RError> {
+    if isolate.is_none() {
+        raplre::throw_rerr!(
+            INVALID_VALUE,
+            "Attributing energy needs an isolate file (-i), record one with `isolate`"
+        );
+    }
+
+    Ok(())
+}
+
+pub(crate) fn cgroup_measurement(
+    session_config: &SessionConfig,
+    run_time_limit: Option<u32>,
+    name: Option<&String>,
+    dir: Option<&PathBuf>,
+    isolate_file: Option<&PathBuf>,
+    cgroup: &Path,
+) -> Result<(), RError> {
+    let system_start_time = SystemTime::now();
+    let run_time_limit = run_time_limit.unwrap_or(0) as u64;
+    let name = name.cloned().unwrap_or("default".to_string());
+    const TOOL_NAME: &str = "cgroup";
+    check_attribution_isolate(isolate_file)?;
+
+    let output_file = match dir {
+        Some(dir) => dir.join(create_log_file_name(&name, TOOL_NAME, None)),
+        None => PathBuf::from(create_log_file_name(&name, TOOL_NAME, None)),
+    };
+
+    let mut session = Session::new(SessionConfig {
+        output_file,
+        isolate_map: read_isolated_data(isolate_file)?,
+        ..session_config.clone()
+    })?;
+    let attribution = CgroupAttribution::open(cgroup)?;
+    println!(
+        "Measuring cgroup {}, press Ctrl-C to stop",
+        attribution.path().display()
+    );
+    session.set_attribution(Box::new(attribution))?;
+
+    crate::signal::catch_termination();
+    let start_time = session.start_time();
+    let handle = session.spawn()?;
+    while crate::signal::received().is_none()
+        && !display::should_terminate(run_time_limit, Instant::now(), start_time)
+    {
+        thread::sleep(Duration::from_millis(CHILD_POLL_DELAY));
+    }
+
+    let session = handle.join()?;
+    let mut summary = create_run_summary(
+        &name,
+        TOOL_NAME,
+        session_config.backend,
+        system_start_time,
+        start_time.elapsed(),
+        session.zones(),
+    );
+    summary.interval = Some(session.interval_stats());
+    summary.sampler = Some(session.overhead());
+    summary.attribution = session.attribution_model();
+    log_run_summary(&create_summary_file_name(session.output_file()), &summary)?;
+
+    print_headers!(false);
+    print_result_line!(session.zones());
+    println!();
+    print_attributed(session.zones(), "the cgroup");
+
+    Ok(())
+}
+
+/// Print the energy attributed to `to` next to the total of every zone.
+fn print_attributed(zones: &[RAPLData], to: &str) {
+    for zone in zones.iter() {
+        if let Some(attributed_j) = zone.attributed_j {
+            println!(
+                "{}: {:.3} J total, {:.3} J attributed to {} ({:.1} %)",
+                zone.zone_name,
+                zone.total_power_j,
+                attributed_j,
+                to,
+                if zone.total_power_j > 0.0 {
+                    attributed_j * 100.0 / zone.total_power_j
+                } else {
+                    0.0
+                }
+            );
+        }
+    }
+}
+
+pub(crate) fn list(backend: Backend, json: bool) -> Result<(), RError> {
+    let probes = probe_zones(backend)?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&probes).map_err(|e| {
+            new_custom_msg(
+                INVALID_VALUE,
+                format!("Failed to serialize probed domains (error: {})", e),
+            )
+        })?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    let headers = vec![
+        "zone", "status", "unit(J)", "range(J)", "backend", "counter", "core",
+    ];
+    let mut line = String::new();
+    for h in headers {
+        line.push_str(format!("{}{}", h, display::spacing(h)).as_str());
+    }
+    println!("{}", line.trim());
+
+    for probe in probes {
+        let fields = vec![
+            probe.name,
+            probe.status.to_string(),
+            format!("{:e}", probe.unit),
+            format!("{:.3}", probe.wrap_range_j),
+            probe.backend.to_string(),
+            probe.counter,
+            probe.core.to_string(),
+        ];
+        let mut line = String::new();
+        for f in fields {
+            line.push_str(format!("{}{}", f, display::spacing(&f)).as_str());
+        }
+        println!("{}", line.trim());
+
+        if let Some(error) = probe.error {
+            println!("    {}", error);
+        }
+        if let Some(info) = probe.power_info {
+            println!(
+                "    TDP {:.3} W, power range {:.3}-{:.3} W, max time window {:.6} s",
+                info.thermal_spec_w, info.min_w, info.max_w, info.max_time_window_s
+            );
+        }
+    }
 
     Ok(())
 }
 
-pub(crate) fn list() -> Result<(), RError> {
-    let zones = common::detect_cpu();
-    for zone in zones {
-        println!("{}", zone.name);
+pub(crate) fn limits(json: bool) -> Result<(), RError> {
+    let mut domains = vec![];
+    for pkg in 0..cpu::topology().max_num_packages() {
+        domains.extend(read_power_limits(pkg)?);
+    }
+
+    if json {
+        let json = serde_json::to_string_pretty(&domains).map_err(|e| {
+            new_custom_msg(
+                INVALID_VALUE,
+                format!("Failed to serialize power limits (error: {})", e),
+            )
+        })?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    let headers = vec!["zone", "limit", "W", "window(s)", "enabled", "clamped"];
+    let mut line = String::new();
+    for h in headers {
+        line.push_str(format!("{}{}", h, display::spacing(h)).as_str());
+    }
+    println!("{}", line.trim());
+
+    for domain in domains {
+        for (idx, limit) in domain.limits.iter().enumerate() {
+            let fields = vec![
+                domain.zone.clone(),
+                format!("PL{}", idx + 1),
+                format!("{:.3}", limit.power_w),
+                format!("{:.6}", limit.time_window_s),
+                limit.enabled.to_string(),
+                limit.clamped.to_string(),
+            ];
+            let mut line = String::new();
+            for f in fields {
+                line.push_str(format!("{}{}", f, display::spacing(&f)).as_str());
+            }
+            println!("{}", line.trim());
+        }
+
+        if domain.locked {
+            println!(
+                "    {} is locked ({:#x} = {:#x})",
+                domain.zone, domain.msr, domain.raw
+            );
+        }
     }
 
     Ok(())
@@ -207,18 +849,18 @@ pub(crate) fn list() -> Result<(), RError> {
 
 pub(crate) fn pretty_print(file: PathBuf) -> Result<(), RError> {
     if !file.exists() {
-        crate::throw_rerr!(NOT_FOUND, "Failed to find `{}`", file.to_str().unwrap());
+        raplre::throw_rerr!(NOT_FOUND, "Failed to find `{}`", file.to_str().unwrap());
     }
 
     if !file.is_file() {
-        crate::throw_rerr!(
+        raplre::throw_rerr!(
             NOT_ALLOWED,
             "Path `{}` is not a file",
             file.to_str().unwrap()
         );
     }
 
-    let last = common::get_last_measurement_from(file);
+    let last = read_last_measurement(&file)?;
 
     print_headers!();
     print_result_line!(&last);
@@ -232,86 +874,7 @@ pub(crate) fn generate_isolate_data(
     name: &String,
     csv_file: PathBuf,
 ) -> Result<(), RError> {
-    if !csv_file.exists() {
-        crate::throw_rerr!(
-            NOT_FOUND,
-            "Failed to open {} (error: not found)",
-            csv_file.to_str().unwrap()
-        );
-    }
-
-    if !csv_file.is_file() {
-        crate::throw_rerr!(
-            NOT_ALLOWED,
-            "Failed to open {} (error: not a file)",
-            csv_file.to_str().unwrap()
-        );
-    }
-
-    let mut rdr = csv::Reader::from_path(&csv_file).map_err(|e| {
-        new_custom_msg(
-            NOT_ALLOWED,
-            format!(
-                "Failed to open {} (error:{})",
-                csv_file.to_str().unwrap(),
-                e
-            ),
-        )
-    })?;
-
-    let zones = common::detect_cpu();
-    let mut map = HashMap::new();
-    let mut out_map = HashMap::new();
-
-    for res in rdr.deserialize() {
-        let r: models::RAPLData = res.map_err(|e| {
-            new_custom_msg(
-                INVALID_VALUE,
-                format!("Failed to deserialize RAPLData (error:{})", e),
-            )
-        })?;
-
-        map.entry(r.zone_name.clone()).or_insert(vec![]).push(r);
-    }
-
-    for zone in zones {
-        let zone_data = map.entry(zone.name.clone()).or_insert(vec![]);
-        zone_data.remove(0);
-        let data_len = zone_data.len();
-        let mut delta_power_j_step = vec![];
-        let mut watts_step = vec![];
-        let mut avg_watts = vec![];
-
-        for n in 0..data_len - 1 {
-            delta_power_j_step.push(zone_data[n].delta_power_j);
-            watts_step.push(zone_data[n].watt);
-            avg_watts.push(zone_data[n].avg_watt);
-        }
-
-        out_map.insert(
-            zone.name,
-            models::IsolateData {
-                delta_power_j: StatData {
-                    min: delta_power_j_step.iter().cloned().fold(0. / 0., f64::min),
-                    max: delta_power_j_step.iter().cloned().fold(0. / 0., f64::max),
-                    avg: delta_power_j_step.iter().sum::<f64>() / data_len as f64,
-                    total: delta_power_j_step.iter().sum(),
-                },
-                watt: StatData {
-                    min: watts_step.iter().cloned().fold(0. / 0., f64::min),
-                    max: watts_step.iter().cloned().fold(0. / 0., f64::max),
-                    avg: watts_step.iter().sum::<f64>() / data_len as f64,
-                    total: watts_step.iter().sum(),
-                },
-                avg_watt: StatData {
-                    min: avg_watts.iter().cloned().fold(0. / 0., f64::min),
-                    max: avg_watts.iter().cloned().fold(0. / 0., f64::max),
-                    avg: avg_watts.iter().sum::<f64>() / data_len as f64,
-                    total: avg_watts.iter().sum(),
-                },
-            },
-        );
-    }
+    let out_map = isolate_data_from_csv(&csv_file)?;
 
     let filename = dir.cloned().unwrap_or_default().join(format!(
         "{}-isolate-{}.json",
@@ -322,13 +885,13 @@ pub(crate) fn generate_isolate_data(
             .as_secs()
     ));
 
-    logger::log_isolate_data(filename, out_map);
+    log_isolate_data(filename, out_map);
 
     Ok(())
 }
 
 pub(crate) fn measure_isolate_data(
-    poll_delay: u32,
+    session_config: &SessionConfig,
     dir: Option<&PathBuf>,
     name: &String,
     mintues: u32,
@@ -343,41 +906,49 @@ pub(crate) fn measure_isolate_data(
             .as_secs()
     ));
 
-    let sleep = std::time::Duration::from_millis(poll_delay as u64);
-    let mut zones = common::setup_rapl_data();
-    assert!(zones.len() != 0);
-    let start_time = Instant::now();
-    let mut prev_time = start_time;
-    #[allow(unused_assignments)]
-    let mut now = start_time;
+    // The residency tells whether the system actually idled.
+    let mut session = match Session::new(SessionConfig {
+        output_file: csv_file.clone(),
+        cstates: true,
+        ..session_config.clone()
+    }) {
+        Ok(session) => session,
+        Err(e) => {
+            display::terminate();
+            return Err(e);
+        }
+    };
+    if let Err(e) = session.setup_sampler_thread() {
+        display::terminate();
+        return Err(e);
+    }
+    let start_time = session.start_time();
     let mintues = mintues.max(1);
-    let total_num_sample = mintues * 60 * 1000 / poll_delay;
+    let total_num_sample = (mintues as f64 * 60.0 / session_config.interval.as_secs_f64()) as u64;
     let mut num_sample = 0;
     let run_time_limit = mintues as u64 * 60;
 
     loop {
-        now = Instant::now();
-        common::update_measurements(
-            &mut zones, now, start_time, prev_time, &csv_file, None, false,
-        );
-        prev_time = now;
+        if let Err(e) = session.sample() {
+            display::terminate();
+            return Err(e);
+        }
 
         ncurses::clear();
         ncurses::addstr(format!("Samples ({}/{})\n", num_sample, total_num_sample).as_str());
         ncurses::refresh();
         num_sample += 1;
 
-        if ncurses::getch() == common::KEY_CODE_EXIT {
+        if ncurses::getch() == display::KEY_CODE_EXIT {
             ncurses::endwin();
             break;
         }
 
-        if common::should_terminate(run_time_limit, now, start_time) {
-            common::terminate();
+        if display::should_terminate(run_time_limit, Instant::now(), start_time) {
+            display::terminate();
             break;
         }
-        std::thread::sleep(sleep);
-        // std::thread::sleep(std::time::Duration::from_secs(100000));
+        session.wait();
     }
 
     generate_isolate_data(dir, name, csv_file)
@@ -391,7 +962,7 @@ pub(crate) fn extract_data(
     csv_file: PathBuf,
 ) -> Result<(), RError> {
     if !csv_file.exists() {
-        crate::throw_rerr!(
+        raplre::throw_rerr!(
             NOT_FOUND,
             "Failed to open {} (error: not found)",
             csv_file.to_str().unwrap()
@@ -399,7 +970,7 @@ pub(crate) fn extract_data(
     }
 
     if !csv_file.is_file() {
-        crate::throw_rerr!(
+        raplre::throw_rerr!(
             NOT_ALLOWED,
             "Failed to open {} (error: not a file)",
             csv_file.to_str().unwrap()
@@ -407,7 +978,7 @@ pub(crate) fn extract_data(
     }
 
     if smooth && (alpha <= 0.0 || alpha >= 1.0) {
-        crate::throw_rerr!(
+        raplre::throw_rerr!(
             INVALID_VALUE,
             "The value of alpha must between 0.0 and 1.0, received {}",
             alpha
@@ -441,7 +1012,7 @@ pub(crate) fn extract_data(
                 map.entry(r.zone_name.clone()).or_insert(vec![]).push(r);
             }
             Err(e) => {
-                crate::throw_rerr!(
+                raplre::throw_rerr!(
                     NOT_ALLOWED,
                     "Failed to parse {} (error: {})",
                     csv_file.to_str().unwrap(),